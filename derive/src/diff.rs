@@ -0,0 +1,122 @@
+//! `#[derive(Diff)]`.
+
+use crate::parse::Struct;
+
+pub(crate) fn expand(s: &Struct) -> String {
+	let Struct {
+		vis, name, fields, ..
+	} = s;
+	let changes = format!("{name}Changes");
+	let mut decl = String::new();
+	let mut init = String::new();
+	let mut unchanged = Vec::new();
+	let mut apply = String::new();
+	let mut bounds = Vec::new();
+	let mut debug = String::new();
+	let mut clone = String::new();
+	let mut eq = Vec::new();
+	for (i, f) in fields.iter().enumerate() {
+		let (member, prefix) = (f.member(i), f.prefix());
+		let ty = format!("<{} as ::cowcell::Diff>::Changes", f.ty);
+		decl += &format!(
+			"{} {prefix}::core::option::Option<{ty}>,",
+			f.vis
+		);
+		init += &format!(
+			"{prefix}::cowcell::Diff::diff(&self.{member}, \
+			 &other.{member}),"
+		);
+		unchanged.push(format!("changes.{member}.is_none()"));
+		apply += &format!(
+			"if let ::core::option::Option::Some(c) = \
+			 &changes.{member} {{
+				::cowcell::Diff::apply(&mut self.{member}, c)?;
+			}}"
+		);
+		bounds.push(ty);
+		debug += &match &f.name {
+			Some(name) => format!(".field({name:?}, &self.{member})"),
+			None => format!(".field(&self.{member})"),
+		};
+		clone += &format!(
+			"{prefix}::core::clone::Clone::clone(&self.{member}),"
+		);
+		eq.push(format!("self.{member} == other.{member}"));
+	}
+	let decl = s.decl(&decl);
+	let init = s.init(&changes, &init);
+	let clone = s.init("Self", &clone);
+	let unchanged = unchanged.join(" && ");
+	let eq = eq.join(" && ");
+	// The impls are bounded on the change types of the fields rather
+	// than derived, as a field type may not implement the traits. The
+	// bounds are higher-ranked so that unsatisfied ones only leave the
+	// impl out instead of failing to compile.
+	let bound = |tr: &str| {
+		let bounds = bounds
+			.iter()
+			.map(|ty| format!("for<'__x> {ty}: {tr},"))
+			.collect::<String>();
+		format!("impl {tr} for {changes} where {bounds}")
+	};
+	let debug_impl = bound("::core::fmt::Debug");
+	let clone_impl = bound("::core::clone::Clone");
+	let eq_impl = bound("::core::cmp::PartialEq");
+	let debug_start = if s.tuple {
+		format!("f.debug_tuple({changes:?})")
+	} else {
+		format!("f.debug_struct({changes:?})")
+	};
+	format!(
+		"
+		/// The changes between two values of [`{name}`], with [`None`]
+		/// for every field that is equal in both.
+		{vis} struct {changes} {decl}
+
+		{debug_impl} {{
+			fn fmt(
+				&self,
+				f: &mut ::core::fmt::Formatter<'_>,
+			) -> ::core::fmt::Result {{
+				{debug_start}{debug}.finish()
+			}}
+		}}
+
+		{clone_impl} {{
+			fn clone(&self) -> Self {{
+				{clone}
+			}}
+		}}
+
+		{eq_impl} {{
+			fn eq(&self, other: &Self) -> bool {{
+				{eq}
+			}}
+		}}
+
+		impl ::cowcell::Diff for {name} {{
+			type Changes = {changes};
+
+			fn diff(
+				&self,
+				other: &Self,
+			) -> ::core::option::Option<{changes}> {{
+				let changes = {init};
+				if {unchanged} {{
+					::core::option::Option::None
+				}} else {{
+					::core::option::Option::Some(changes)
+				}}
+			}}
+
+			fn apply(
+				&mut self,
+				changes: &{changes},
+			) -> ::core::result::Result<(), ::cowcell::PatchConflict> {{
+				{apply}
+				::core::result::Result::Ok(())
+			}}
+		}}
+		"
+	)
+}
@@ -0,0 +1,62 @@
+//! `#[derive(CowFields)]`.
+
+use crate::parse::Struct;
+
+pub(crate) fn expand(s: &Struct) -> String {
+	let Struct {
+		vis, name, fields, ..
+	} = s;
+	let view = format!("{name}Fields");
+	let mut decl = String::new();
+	let mut init = String::new();
+	let mut inner = String::new();
+	let mut cloned = Vec::new();
+	for (i, f) in fields.iter().enumerate() {
+		let (member, prefix) = (f.member(i), f.prefix());
+		decl += &format!(
+			"{} {prefix}::cowcell::CowField<'cow, {}>,",
+			f.vis, f.ty
+		);
+		init += &format!(
+			"{prefix}::cowcell::CowField::new(&self.{member}),"
+		);
+		inner += &format!("{prefix}self.{member}.into_inner(),");
+		cloned.push(format!("self.{member}.is_cloned()"));
+	}
+	let decl = s.decl(&decl);
+	let init = s.init(&view, &init);
+	let inner = s.init(name, &inner);
+	let cloned = cloned.join(" || ");
+	format!(
+		"
+		/// A view over the fields of [`{name}`], each of them cloned
+		/// independently on mutable access.
+		{vis} struct {view}<'cow> {decl}
+
+		impl ::cowcell::CowFields for {name} {{
+			type Fields<'cow> = {view}<'cow> where Self: 'cow;
+
+			#[inline]
+			fn fields(&self) -> Self::Fields<'_> {{
+				{init}
+			}}
+		}}
+
+		impl<'cow> {view}<'cow> {{
+			/// Returns `true` if any field has made a copy of the
+			/// original value.
+			#[inline]
+			{vis} fn is_cloned(&self) -> bool {{
+				{cloned}
+			}}
+
+			/// Assemble an owned [`{name}`] from the modified fields,
+			/// cloning the untouched ones.
+			#[inline]
+			{vis} fn into_inner(self) -> {name} {{
+				{inner}
+			}}
+		}}
+		"
+	)
+}
@@ -0,0 +1,86 @@
+//! Derive macros for the `cowcell` crate.
+//!
+//! ```rust
+//! use cowcell::{CowCell, CowFields};
+//!
+//! #[derive(CowFields)]
+//! struct Config {
+//!     name: String,
+//!     data: Vec<u8>,
+//! }
+//!
+//! let cell = CowCell::new(Config {
+//!     name: "a".into(),
+//!     data: vec![0; 4096],
+//! });
+//! let borrow = cell.borrow();
+//! let mut fields = borrow.fields();
+//!
+//! // Only the name is cloned.
+//! fields.name.push('b');
+//! assert!(fields.name.is_cloned());
+//! assert!(!fields.data.is_cloned());
+//!
+//! let config = fields.into_inner();
+//! assert_eq!(config.name, "ab");
+//! assert_eq!(borrow.name, "a");
+//! ```
+//!
+//! ```rust
+//! use cowcell::{CowCell, Diff};
+//!
+//! #[derive(Clone, Diff)]
+//! struct Config {
+//!     name: String,
+//!     retries: Option<u32>,
+//! }
+//!
+//! let cell = CowCell::new(Config {
+//!     name: "a".into(),
+//!     retries: None,
+//! });
+//! let mut borrow = cell.borrow();
+//! borrow.name.push('b');
+//!
+//! let changes = borrow.diff().unwrap().unwrap();
+//! assert_eq!(changes.name.as_deref(), Some("ab"));
+//! assert_eq!(changes.retries, None);
+//!
+//! let patched = borrow.patch().unwrap().apply(&cell).unwrap();
+//! assert_eq!(patched.name, "ab");
+//! ```
+
+mod diff;
+mod fields;
+mod parse;
+
+use parse::Struct;
+use proc_macro::TokenStream;
+
+/// Parse `input` and expand it with `expand`, or report the parse
+/// error.
+fn derive(
+	input: TokenStream,
+	name: &str,
+	expand: fn(&Struct) -> String,
+) -> TokenStream {
+	let out = match Struct::parse(input, name) {
+		Ok(s) => expand(&s),
+		Err(msg) => format!("compile_error!({msg:?});"),
+	};
+	out.parse().unwrap()
+}
+
+/// Generate a `<Name>Fields` view with a `CowField` for every field
+/// of the struct, and implement `CowFields` for it.
+#[proc_macro_derive(CowFields)]
+pub fn derive_cow_fields(input: TokenStream) -> TokenStream {
+	derive(input, "CowFields", fields::expand)
+}
+
+/// Generate a `<Name>Changes` struct with the optional changes of
+/// every field of the struct, and implement `Diff` for it.
+#[proc_macro_derive(Diff)]
+pub fn derive_diff(input: TokenStream) -> TokenStream {
+	derive(input, "Diff", diff::expand)
+}
@@ -0,0 +1,205 @@
+//! A minimal parser for the structs given to the derive macros.
+
+use proc_macro::{Delimiter, Spacing, TokenStream, TokenTree};
+use std::iter::Peekable;
+
+type Tokens = Peekable<std::vec::IntoIter<TokenTree>>;
+
+pub(crate) struct Field {
+	pub(crate) vis: String,
+	/// `None` for fields of tuple structs.
+	pub(crate) name: Option<String>,
+	pub(crate) ty: String,
+}
+
+pub(crate) struct Struct {
+	pub(crate) vis: String,
+	pub(crate) name: String,
+	pub(crate) fields: Vec<Field>,
+	pub(crate) tuple: bool,
+}
+
+impl Struct {
+	/// Parse a struct with fields and no generics, returning an error
+	/// message for `derive` otherwise.
+	pub(crate) fn parse(
+		input: TokenStream,
+		derive: &str,
+	) -> Result<Self, String> {
+		let mut tokens = input
+			.into_iter()
+			.collect::<Vec<_>>()
+			.into_iter()
+			.peekable();
+		skip_attrs(&mut tokens);
+		let vis = parse_vis(&mut tokens);
+		match tokens.next() {
+			Some(TokenTree::Ident(i))
+				if i.to_string() == "struct" => {}
+			_ => {
+				return Err(format!(
+					"{derive} can only be derived for structs"
+				))
+			}
+		}
+		let Some(TokenTree::Ident(name)) = tokens.next() else {
+			return Err("expected struct name".into());
+		};
+		let (fields, tuple) = match tokens.next() {
+			Some(TokenTree::Group(g))
+				if g.delimiter() == Delimiter::Brace =>
+			{
+				(parse_fields(g.stream(), true)?, false)
+			}
+			Some(TokenTree::Group(g))
+				if g.delimiter() == Delimiter::Parenthesis =>
+			{
+				(parse_fields(g.stream(), false)?, true)
+			}
+			Some(TokenTree::Punct(p)) if p.as_char() == '<' => {
+				return Err(format!(
+					"{derive} cannot be derived for generic structs"
+				))
+			}
+			_ => Default::default(),
+		};
+		if fields.is_empty() {
+			return Err(format!(
+				"{derive} cannot be derived for structs without fields"
+			));
+		}
+		Ok(Self {
+			vis,
+			name: name.to_string(),
+			fields,
+			tuple,
+		})
+	}
+}
+
+impl Struct {
+	/// Wrap comma-terminated field declarations into the body of a
+	/// struct of the same kind.
+	pub(crate) fn decl(&self, fields: &str) -> String {
+		if self.tuple {
+			format!("({fields});")
+		} else {
+			format!("{{{fields}}}")
+		}
+	}
+
+	/// Wrap comma-terminated field initializers into an expression
+	/// building a struct of the same kind at `path`.
+	pub(crate) fn init(&self, path: &str, fields: &str) -> String {
+		if self.tuple {
+			format!("{path}({fields})")
+		} else {
+			format!("{path} {{{fields}}}")
+		}
+	}
+}
+
+impl Field {
+	/// The member used to access this field, given its index.
+	pub(crate) fn member(&self, index: usize) -> String {
+		match &self.name {
+			Some(name) => name.clone(),
+			None => index.to_string(),
+		}
+	}
+
+	/// The prefix naming this field in declarations and
+	/// initializers, empty for fields of tuple structs.
+	pub(crate) fn prefix(&self) -> String {
+		match &self.name {
+			Some(name) => format!("{name}: "),
+			None => String::new(),
+		}
+	}
+}
+
+/// Parse the fields in the body of a struct.
+fn parse_fields(
+	body: TokenStream,
+	named: bool,
+) -> Result<Vec<Field>, String> {
+	let mut fields = Vec::new();
+	for field in split_fields(body) {
+		let mut tokens = field.into_iter().peekable();
+		skip_attrs(&mut tokens);
+		let vis = parse_vis(&mut tokens);
+		let name = if named {
+			let Some(TokenTree::Ident(name)) = tokens.next() else {
+				return Err("expected field name".into());
+			};
+			match tokens.next() {
+				Some(TokenTree::Punct(p)) if p.as_char() == ':' => {}
+				_ => {
+					return Err("expected `:` after field name".into())
+				}
+			}
+			Some(name.to_string())
+		} else {
+			None
+		};
+		let ty = tokens.collect::<TokenStream>().to_string();
+		fields.push(Field { vis, name, ty });
+	}
+	Ok(fields)
+}
+
+/// Split the body of a struct on the commas separating its fields,
+/// skipping the ones inside generic arguments.
+fn split_fields(body: TokenStream) -> Vec<Vec<TokenTree>> {
+	let mut fields = Vec::new();
+	let mut cur = Vec::new();
+	let mut depth = 0usize;
+	let mut arrow = false;
+	for tt in body {
+		if let TokenTree::Punct(p) = &tt {
+			match p.as_char() {
+				'<' => depth += 1,
+				'>' if !arrow => depth = depth.saturating_sub(1),
+				',' if depth == 0 => {
+					fields.push(std::mem::take(&mut cur));
+					arrow = false;
+					continue;
+				}
+				_ => {}
+			}
+			arrow =
+				p.as_char() == '-' && p.spacing() == Spacing::Joint;
+		} else {
+			arrow = false;
+		}
+		cur.push(tt);
+	}
+	if !cur.is_empty() {
+		fields.push(cur);
+	}
+	fields
+}
+
+/// Skip outer attributes, including doc comments.
+fn skip_attrs(tokens: &mut Tokens) {
+	while matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '#')
+	{
+		tokens.next();
+		tokens.next();
+	}
+}
+
+/// Parse an optional visibility, such as `pub` or `pub(crate)`.
+fn parse_vis(tokens: &mut Tokens) -> String {
+	match tokens.peek() {
+		Some(TokenTree::Ident(i)) if i.to_string() == "pub" => {}
+		_ => return String::new(),
+	}
+	let mut vis = tokens.next().unwrap().to_string();
+	if let Some(TokenTree::Group(g)) = tokens.peek() {
+		if g.delimiter() == Delimiter::Parenthesis {
+			vis += &tokens.next().unwrap().to_string();
+		}
+	}
+	vis
+}
@@ -0,0 +1,80 @@
+use cowcell::diff::OptionChange;
+use cowcell::{CowCell, Diff, PatchConflict};
+
+#[derive(Debug, Clone, PartialEq, Diff)]
+struct Point(i32, i32);
+
+#[derive(Clone, Diff)]
+struct Line {
+	start: Point,
+	end: Point,
+}
+
+// Implements neither Debug nor PartialEq, which must not keep
+// containers of it from being diffed.
+#[derive(Clone, Diff)]
+struct Opaque {
+	id: u32,
+}
+
+#[derive(Clone, Diff)]
+struct Holder {
+	opt: Option<Opaque>,
+	list: Vec<Opaque>,
+}
+
+#[test]
+fn tuple_struct() {
+	let changes = Point(1, 2).diff(&Point(1, 3)).unwrap();
+	assert_eq!(changes, PointChanges(None, Some(3)));
+	assert_eq!(format!("{changes:?}"), "PointChanges(None, Some(3))");
+	assert_eq!(Point(1, 2).diff(&Point(1, 2)), None);
+
+	let mut p = Point(0, 0);
+	p.apply(&changes).unwrap();
+	assert_eq!(p, Point(0, 3));
+}
+
+#[test]
+fn nested() {
+	let cell = CowCell::new(Line {
+		start: Point(0, 0),
+		end: Point(1, 1),
+	});
+	let mut borrow = cell.borrow();
+	borrow.end.1 = 2;
+	let changes = borrow.diff().unwrap().unwrap();
+	assert_eq!(changes.start, None);
+	assert_eq!(changes.end, Some(PointChanges(None, Some(2))));
+	assert_eq!(changes.clone(), changes);
+
+	let patched = borrow.patch().unwrap().apply(&cell).unwrap();
+	assert_eq!(patched.end, Point(1, 2));
+}
+
+#[test]
+fn fields_without_debug() {
+	let old = Holder {
+		opt: Some(Opaque { id: 1 }),
+		list: vec![Opaque { id: 2 }],
+	};
+	let mut new = old.clone();
+	new.opt = None;
+	new.list[0].id = 3;
+	let changes = old.diff(&new).unwrap();
+	assert!(matches!(changes.opt, Some(OptionChange::Removed)));
+
+	let mut val = old.clone();
+	val.apply(&changes).unwrap();
+	assert!(val.opt.is_none());
+	assert_eq!(val.list[0].id, 3);
+	assert_eq!(
+		Holder {
+			opt: None,
+			list: vec![]
+		}
+		.apply(&changes)
+		.err(),
+		Some(PatchConflict)
+	);
+}
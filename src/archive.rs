@@ -0,0 +1,202 @@
+//! Borrows of archived values, deserialized on mutable access.
+
+use core::fmt;
+#[cfg(feature = "rkyv")]
+use rkyv::{api::high::HighDeserializer, rancor::Panic, Archived};
+
+/// A borrow of an archived value, such as an `rkyv` archive in
+/// memory-mapped data, with deserialize-on-write semantics.
+///
+/// Reads go to the archived value without deserializing it. The first
+/// mutable access deserializes it with `D` into an owned `T`, which
+/// the borrow then reads and writes instead. With the `rkyv` feature,
+/// `CowRkyv` borrows `rkyv` archives and deserializes them with
+/// `rkyv::deserialize`.
+///
+/// ```rust
+/// # #[cfg(feature = "rkyv")] {
+/// use cowcell::{ArchivedRef, CowRkyv};
+/// use rkyv::rancor::Error;
+/// use rkyv::Archived;
+///
+/// // Bytes as they would be read from a memory-mapped file.
+/// let bytes = rkyv::to_bytes::<Error>(&vec![1u32, 2, 3]).unwrap();
+/// let archived = rkyv::access::<Archived<Vec<u32>>, Error>(&bytes).unwrap();
+///
+/// let mut borrow = CowRkyv::<Vec<u32>>::from_rkyv(archived);
+/// assert!(matches!(borrow.get_ref(), ArchivedRef::Archived(v) if v[2] == 3));
+///
+/// borrow.get_mut().push(4);
+/// assert!(borrow.is_deserialized());
+/// assert_eq!(borrow.into_inner(), [1, 2, 3, 4]);
+/// # }
+/// ```
+pub struct CowArchived<'a, A: ?Sized, T, D> {
+	ptr: &'a A,
+	owned: Option<T>,
+	deserialize: D,
+}
+
+/// The current value of a [`CowArchived`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchivedRef<'b, A: ?Sized, T> {
+	/// The archived value, which has not been deserialized.
+	Archived(&'b A),
+	/// The deserialized value.
+	Owned(&'b T),
+}
+
+impl<'a, A: ?Sized, T, D: FnMut(&A) -> T> CowArchived<'a, A, T, D> {
+	/// A new borrow of `ptr`, deserialized with `deserialize` on
+	/// mutable access.
+	#[inline]
+	pub const fn new(ptr: &'a A, deserialize: D) -> Self {
+		Self {
+			ptr,
+			owned: None,
+			deserialize,
+		}
+	}
+
+	/// Returns the archived value this borrow was made from.
+	#[inline]
+	pub const fn get_archived(&self) -> &'a A {
+		self.ptr
+	}
+
+	/// Get the current value, which is the archived one until it is
+	/// deserialized.
+	#[inline]
+	pub const fn get_ref(&self) -> ArchivedRef<'_, A, T> {
+		match self.owned.as_ref() {
+			Some(v) => ArchivedRef::Owned(v),
+			None => ArchivedRef::Archived(self.ptr),
+		}
+	}
+
+	/// Returns [`true`] if this [`CowArchived`] has deserialized the
+	/// archived value.
+	#[inline]
+	pub const fn is_deserialized(&self) -> bool {
+		self.owned.is_some()
+	}
+
+	/// Get a mutable reference to the deserialized value,
+	/// deserializing it if necessary.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		let ptr = self.ptr;
+		let deserialize = &mut self.deserialize;
+		self.owned.get_or_insert_with(|| deserialize(ptr))
+	}
+
+	/// Consume the [`CowArchived`], retrieving the deserialized value.
+	/// This deserializes the archived value if it was not already.
+	#[inline]
+	pub fn into_inner(mut self) -> T {
+		match self.owned.take() {
+			Some(v) => v,
+			None => (self.deserialize)(self.ptr),
+		}
+	}
+}
+
+/// A [`CowArchived`] of an `rkyv` archive of a `T`, created with
+/// [`CowArchived::from_rkyv`].
+#[cfg(feature = "rkyv")]
+pub type CowRkyv<'a, T> =
+	CowArchived<'a, Archived<T>, T, fn(&Archived<T>) -> T>;
+
+#[cfg(feature = "rkyv")]
+impl<'a, T> CowRkyv<'a, T>
+where
+	T: rkyv::Archive,
+	Archived<T>: rkyv::Deserialize<T, HighDeserializer<Panic>>,
+{
+	/// A new borrow of an `rkyv` archive, deserialized with
+	/// `rkyv::deserialize` on mutable access.
+	///
+	/// # Panics
+	///
+	/// On mutable access, if deserializing fails.
+	#[inline]
+	pub fn from_rkyv(archived: &'a Archived<T>) -> Self {
+		Self::new(archived, deserialize_rkyv::<T>)
+	}
+}
+
+/// Deserialize an `rkyv` archive, panicking on failure.
+#[cfg(feature = "rkyv")]
+fn deserialize_rkyv<T>(archived: &Archived<T>) -> T
+where
+	T: rkyv::Archive,
+	Archived<T>: rkyv::Deserialize<T, HighDeserializer<Panic>>,
+{
+	let Ok(val) = rkyv::deserialize::<T, Panic>(archived);
+	val
+}
+
+impl<A, T, D> fmt::Debug for CowArchived<'_, A, T, D>
+where
+	A: ?Sized + fmt::Debug,
+	T: fmt::Debug,
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let val: &dyn fmt::Debug = match self.owned.as_ref() {
+			Some(v) => v,
+			None => &self.ptr,
+		};
+		f.debug_struct("CowArchived")
+			.field("val", val)
+			.field("is_deserialized", &self.owned.is_some())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn deserializes_once() {
+		let archived = [1u8, 2];
+		let mut runs = 0;
+		let mut borrow =
+			CowArchived::new(&archived[..], |a: &[u8]| {
+				runs += 1;
+				a.iter().map(|&b| u32::from(b)).sum::<u32>()
+			});
+		assert_eq!(
+			borrow.get_ref(),
+			ArchivedRef::Archived(&[1, 2][..])
+		);
+		*borrow.get_mut() += 1;
+		*borrow.get_mut() += 1;
+		assert_eq!(borrow.get_ref(), ArchivedRef::Owned(&5));
+		assert_eq!(borrow.into_inner(), 5);
+		assert_eq!(runs, 1);
+	}
+
+	#[test]
+	#[cfg(feature = "rkyv")]
+	fn from_rkyv() {
+		use rkyv::rancor::Error;
+
+		let val = vec![String::from("a"), String::from("b")];
+		let bytes = rkyv::to_bytes::<Error>(&val).unwrap();
+		let archived =
+			rkyv::access::<Archived<Vec<String>>, Error>(&bytes)
+				.unwrap();
+		let mut borrow = CowRkyv::<Vec<String>>::from_rkyv(archived);
+		let ArchivedRef::Archived(v) = borrow.get_ref() else {
+			panic!("deserialized on read");
+		};
+		assert_eq!(v[1], "b");
+		borrow.get_mut()[0].push('c');
+		assert_eq!(
+			borrow.get_ref(),
+			ArchivedRef::Owned(&vec!["ac".into(), "b".into()])
+		);
+		assert_eq!(archived[0], "a");
+	}
+}
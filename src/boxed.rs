@@ -0,0 +1,76 @@
+//! Boxed cells holding unsized values.
+
+use crate::CowCell;
+use alloc::alloc::{alloc, handle_alloc_error, Layout};
+use alloc::boxed::Box;
+use core::mem::offset_of;
+use core::ptr;
+
+/// Copy `src` into a new boxed [`CowCell`].
+///
+/// ```rust
+/// use cowcell::CowCell;
+///
+/// let cell: Box<CowCell<[u8]>> = Box::from(&b"abc"[..]);
+/// let mut borrow = cell.borrow();
+/// assert_eq!(&*borrow, b"abc");
+///
+/// // The copy of a slice is a `Vec`.
+/// borrow.get_mut().push(b'd');
+/// assert_eq!(&*borrow, b"abcd");
+/// ```
+impl<T: Copy> From<&[T]> for Box<CowCell<[T]>> {
+	fn from(src: &[T]) -> Self {
+		// `CowCell` is `repr(C)`, so the value of a `CowCell<[T]>`
+		// sits at the same offset as in a `CowCell<[T; 0]>`.
+		let offset = offset_of!(CowCell<[T; 0]>, val);
+		let align = Layout::new::<CowCell<[T; 0]>>().align();
+		let size = Layout::array::<T>(src.len())
+			.ok()
+			.and_then(|l| l.size().checked_add(offset))
+			.expect("slice too large");
+		let layout = Layout::from_size_align(size, align)
+			.expect("slice too large")
+			.pad_to_align();
+		// SAFETY: the layout is never zero-sized, as it contains the
+		// header of the cell. The allocation is initialized with a
+		// valid header followed by `src.len()` elements before being
+		// turned into a box, and it has the layout the box expects.
+		unsafe {
+			let raw = alloc(layout);
+			if raw.is_null() {
+				handle_alloc_error(layout);
+			}
+			raw.cast::<CowCell<[T; 0]>>().write(CowCell::new([]));
+			raw.add(offset)
+				.cast::<T>()
+				.copy_from_nonoverlapping(src.as_ptr(), src.len());
+			let fat = ptr::slice_from_raw_parts_mut(raw, src.len());
+			Box::from_raw(fat as *mut CowCell<[T]>)
+		}
+	}
+}
+
+/// Copy `src` into a new boxed [`CowCell`].
+///
+/// ```rust
+/// use cowcell::CowCell;
+///
+/// let cell: Box<CowCell<str>> = Box::from("abc");
+/// let mut borrow = cell.borrow();
+///
+/// // The copy of a `str` is a `String`.
+/// borrow.get_mut().push('d');
+/// assert_eq!(&*borrow, "abcd");
+/// ```
+impl From<&str> for Box<CowCell<str>> {
+	#[inline]
+	fn from(src: &str) -> Self {
+		let bytes = Box::<CowCell<[u8]>>::from(src.as_bytes());
+		// SAFETY: `str` has the same layout as `[u8]`, and the bytes
+		// are valid UTF-8.
+		unsafe {
+			Box::from_raw(Box::into_raw(bytes) as *mut CowCell<str>)
+		}
+	}
+}
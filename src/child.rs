@@ -0,0 +1,243 @@
+//! Nested clone-on-write borrows of other borrows.
+
+use crate::{CowClone, CowRef};
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+/// A borrow that a [`ChildRef`] can be taken from.
+///
+/// This is implemented for [`CowRef`], whose children commit into its
+/// copy instead of the cell, and for [`ChildRef`] itself, so that
+/// borrows can be stacked to any depth.
+pub trait CowLayer {
+	/// The type of the borrowed value.
+	type Target: Clone;
+
+	/// Get an immutable reference to the current value of the layer.
+	fn get_ref(&self) -> &Self::Target;
+
+	/// Get a mutable reference to a copy of the value of the layer,
+	/// making it if necessary.
+	fn get_mut(&mut self) -> &mut Self::Target;
+
+	/// Replace the value of the layer with a copy of it.
+	fn set(&mut self, val: Self::Target);
+}
+
+impl<'a, T: Clone, C: CowClone<T, Owned = T>> CowLayer
+	for CowRef<'a, T, C>
+{
+	type Target = T;
+
+	#[inline]
+	fn get_ref(&self) -> &T {
+		CowRef::get_ref(self)
+	}
+
+	#[inline]
+	fn get_mut(&mut self) -> &mut T {
+		CowRef::get_mut(self)
+	}
+
+	#[inline]
+	fn set(&mut self, val: T) {
+		self.set_copy(val);
+	}
+}
+
+impl<'a, T: Clone, C: CowClone<T, Owned = T>> CowRef<'a, T, C> {
+	/// Create a child borrow that reads through this one, whether it
+	/// has made a copy or not, and commits into its copy.
+	///
+	/// ```rust
+	/// use cowcell::CowCell;
+	///
+	/// let defaults = CowCell::new(vec!["a"]);
+	/// let mut site = defaults.borrow();
+	/// site.push("b");
+	///
+	/// let mut request = site.child();
+	/// assert_eq!(*request, ["a", "b"]);
+	/// request.push("c");
+	/// request.commit();
+	///
+	/// assert_eq!(*site, ["a", "b", "c"]);
+	/// assert_eq!(*defaults.borrow(), ["a"]);
+	/// ```
+	#[inline]
+	pub fn child(&mut self) -> ChildRef<'_, Self> {
+		ChildRef::new(self)
+	}
+}
+
+/// A borrow of another borrow with clone-on-write semantics on
+/// mutable access.
+///
+/// The child holds its parent exclusively, so the parent cannot
+/// change while the child reads it. Committing the child replaces the
+/// value of the parent with the copy of the child, which is never
+/// rejected.
+pub struct ChildRef<'p, P: CowLayer> {
+	parent: &'p mut P,
+	copy: Option<P::Target>,
+}
+
+impl<'p, P: CowLayer> ChildRef<'p, P> {
+	/// A new child borrow of `parent`.
+	#[inline]
+	pub fn new(parent: &'p mut P) -> Self {
+		Self { parent, copy: None }
+	}
+
+	/// Returns a reference to the parent of this borrow.
+	#[inline]
+	pub fn get_parent(&self) -> &P {
+		self.parent
+	}
+
+	/// Get an immutable reference to the inner value.
+	#[inline]
+	pub fn get_ref(&self) -> &P::Target {
+		match self.copy.as_ref() {
+			Some(v) => v,
+			None => self.parent.get_ref(),
+		}
+	}
+
+	/// Returns [`true`] if this [`ChildRef`] has made a copy of the
+	/// value of its parent.
+	#[inline]
+	pub const fn is_cloned(&self) -> bool {
+		self.copy.is_some()
+	}
+
+	/// Get a mutable reference to the inner value, cloning the value
+	/// of the parent if necessary.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut P::Target {
+		if self.copy.is_none() {
+			self.copy = Some(self.parent.get_ref().clone());
+		}
+		self.copy.as_mut().unwrap()
+	}
+
+	/// Consume the [`ChildRef`], retrieving the inner value. This
+	/// clones the value of the parent if a copy was not already made.
+	#[inline]
+	pub fn into_inner(self) -> P::Target {
+		match self.copy {
+			Some(v) => v,
+			None => self.parent.get_ref().clone(),
+		}
+	}
+
+	/// Replace the value of the parent with the copy made by this
+	/// borrow, if any.
+	#[inline]
+	pub fn commit(self) {
+		if let Some(copy) = self.copy {
+			self.parent.set(copy);
+		}
+	}
+
+	/// Create a child borrow of this one.
+	#[inline]
+	pub fn child(&mut self) -> ChildRef<'_, Self> {
+		ChildRef::new(self)
+	}
+}
+
+impl<P: CowLayer> CowLayer for ChildRef<'_, P> {
+	type Target = P::Target;
+
+	#[inline]
+	fn get_ref(&self) -> &P::Target {
+		ChildRef::get_ref(self)
+	}
+
+	#[inline]
+	fn get_mut(&mut self) -> &mut P::Target {
+		ChildRef::get_mut(self)
+	}
+
+	#[inline]
+	fn set(&mut self, val: P::Target) {
+		self.copy = Some(val);
+	}
+}
+
+impl<P: CowLayer> Deref for ChildRef<'_, P> {
+	type Target = P::Target;
+
+	#[inline]
+	fn deref(&self) -> &Self::Target {
+		self.get_ref()
+	}
+}
+
+impl<P: CowLayer> DerefMut for ChildRef<'_, P> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.get_mut()
+	}
+}
+
+impl<P: CowLayer> fmt::Debug for ChildRef<'_, P>
+where
+	P::Target: fmt::Debug,
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("ChildRef")
+			.field("val", self.get_ref())
+			.field("is_cloned", &self.is_cloned())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::CowCell;
+
+	#[test]
+	fn stacked() {
+		let cell = CowCell::new(0);
+		let mut a = cell.borrow();
+		let mut b = a.child();
+		let mut c = b.child();
+		*c += 1;
+		c.commit();
+		assert!(b.is_cloned());
+		// Children that are not committed are discarded.
+		*b.child() += 1;
+		b.commit();
+		assert_eq!(*a, 1);
+		a.commit().unwrap();
+		assert_eq!(*cell.borrow(), 1);
+	}
+
+	#[test]
+	fn uncloned_child_keeps_parent() {
+		let cell = CowCell::new(0);
+		let mut a = cell.borrow();
+		a.child().commit();
+		assert!(!a.is_cloned());
+	}
+
+	#[test]
+	#[cfg(feature = "alloc")]
+	fn commit_recycles_parent_copy() {
+		use crate::CowPool;
+
+		let pool = CowPool::new(4);
+		let cell = CowCell::new(vec![1]);
+		let mut a = cell.borrow_with(&pool);
+		a.push(2);
+		let mut b = a.child();
+		b.push(3);
+		b.commit();
+		assert_eq!(pool.len(), 1);
+		assert_eq!(*a, [1, 2, 3]);
+		drop(a);
+		assert_eq!(pool.len(), 2);
+	}
+}
@@ -0,0 +1,408 @@
+//! Chunk-granular clone-on-write borrows of slices.
+
+use crate::state::ReadGuard;
+use crate::{CommitError, CowCell};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+const BITS: usize = u64::BITS as usize;
+
+/// A borrow of a slice that copies it one fixed-size chunk at a time.
+///
+/// Only the chunks that are written to are cloned, and are kept by
+/// index until they are committed, with a bitmap tracking which ones
+/// are dirty. The slice can be any `S` that
+/// dereferences to `[T]` through [`AsRef`], such as a `[T]` or a
+/// `Vec<T>`, and can be viewed as a 2-D array with
+/// [`CowChunks::with_cols`].
+///
+/// The borrow reads the original value of the cell for as long as it
+/// is alive, so unlike a [`CowRef`](crate::CowRef) it blocks commits
+/// from other borrows even after making copies. Its own dirty chunks
+/// are published with [`CowChunks::commit`], which writes them into
+/// the cell in place.
+///
+/// ```rust
+/// use cowcell::CowCell;
+///
+/// // A 4x4 matrix, copied one row at a time.
+/// let cell = CowCell::new(vec![0.0f32; 16]);
+/// let mut grid = cell.chunks(4).with_cols(4);
+/// *grid.at_mut(2, 1) = 1.0;
+///
+/// assert_eq!(grid.row(2).collect::<Vec<_>>(), [&0.0, &1.0, &0.0, &0.0]);
+/// assert_eq!(grid.column(1).filter(|v| **v != 0.0).count(), 1);
+/// assert_eq!(grid.dirty_chunks().map(|(i, _)| i).collect::<Vec<_>>(), [2]);
+///
+/// grid.commit().unwrap();
+/// assert_eq!(cell.borrow()[9], 1.0);
+/// ```
+pub struct CowChunks<'a, T, S: ?Sized = [T]> {
+	ptr: &'a CowCell<S>,
+	read: ReadGuard<'a>,
+	chunk_len: usize,
+	cols: usize,
+	copies: BTreeMap<usize, Box<[T]>>,
+	dirty: Vec<u64>,
+}
+
+impl<S: ?Sized> CowCell<S> {
+	/// Create a new borrow that copies the slice in chunks of
+	/// `chunk_len` elements.
+	///
+	/// # Panics
+	///
+	/// If `chunk_len` is zero.
+	#[inline]
+	pub fn chunks<T>(&self, chunk_len: usize) -> CowChunks<'_, T, S>
+	where
+		S: AsRef<[T]>,
+	{
+		CowChunks::new(self, chunk_len)
+	}
+}
+
+impl<'a, T, S: ?Sized + AsRef<[T]>> CowChunks<'a, T, S> {
+	/// A new borrow that copies the slice of `ptr` in chunks of
+	/// `chunk_len` elements.
+	///
+	/// # Panics
+	///
+	/// If `chunk_len` is zero.
+	pub fn new(ptr: &'a CowCell<S>, chunk_len: usize) -> Self {
+		assert!(chunk_len > 0, "zero chunk length");
+		let read = ptr.state.read();
+		// SAFETY: we hold a read on the cell.
+		let len = unsafe { ptr.get_unchecked() }.as_ref().len();
+		let count = len.div_ceil(chunk_len);
+		Self {
+			ptr,
+			read,
+			chunk_len,
+			cols: len.max(1),
+			copies: BTreeMap::new(),
+			dirty: vec![0; count.div_ceil(BITS)],
+		}
+	}
+
+	/// View the slice as a 2-D array in row-major order, with `cols`
+	/// elements per row.
+	///
+	/// # Panics
+	///
+	/// If `cols` is zero or does not divide the length of the slice.
+	pub fn with_cols(mut self, cols: usize) -> Self {
+		assert!(
+			cols > 0 && self.len().is_multiple_of(cols),
+			"length is not a multiple of the row length"
+		);
+		self.cols = cols;
+		self
+	}
+
+	/// Returns a reference to the [`CowCell`] that originated this
+	/// borrow.
+	#[inline]
+	pub const fn get_cell(&self) -> &CowCell<S> {
+		self.ptr
+	}
+
+	/// The original slice.
+	#[inline]
+	fn base(&self) -> &[T] {
+		// SAFETY: this borrow holds a read on the cell.
+		unsafe { self.ptr.get_unchecked() }.as_ref()
+	}
+
+	/// Returns the number of elements in the slice.
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.base().len()
+	}
+
+	/// Returns [`true`] if the slice is empty.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Returns the number of elements in a chunk. The last chunk may
+	/// be shorter.
+	#[inline]
+	pub const fn chunk_len(&self) -> usize {
+		self.chunk_len
+	}
+
+	/// Returns the number of chunks in the slice.
+	#[inline]
+	pub fn chunk_count(&self) -> usize {
+		self.len().div_ceil(self.chunk_len)
+	}
+
+	/// Returns [`true`] if chunk `i` has been copied.
+	#[inline]
+	pub fn is_dirty(&self, i: usize) -> bool {
+		self.dirty
+			.get(i / BITS)
+			.is_some_and(|w| w & (1 << (i % BITS)) != 0)
+	}
+
+	/// Returns the indices and contents of the chunks that have been
+	/// copied, in ascending order.
+	#[inline]
+	pub fn dirty_chunks(
+		&self,
+	) -> impl Iterator<Item = (usize, &[T])> + '_ {
+		self.copies.iter().map(|(&i, chunk)| (i, &**chunk))
+	}
+
+	/// The range of elements covered by chunk `i`.
+	#[inline]
+	fn range(&self, i: usize) -> Range<usize> {
+		let start = i * self.chunk_len;
+		start..self.len().min(start + self.chunk_len)
+	}
+
+	/// Get an immutable reference to chunk `i`.
+	///
+	/// # Panics
+	///
+	/// If `i` is out of bounds.
+	pub fn chunk(&self, i: usize) -> &[T] {
+		assert!(i < self.chunk_count(), "chunk out of bounds");
+		if self.is_dirty(i) {
+			&self.copies[&i]
+		} else {
+			&self.base()[self.range(i)]
+		}
+	}
+
+	/// Returns an iterator over the chunks of the slice.
+	#[inline]
+	pub fn iter(&self) -> impl Iterator<Item = &[T]> + '_ {
+		(0..self.chunk_count()).map(|i| self.chunk(i))
+	}
+
+	/// Returns a reference to the element at index `i`.
+	#[inline]
+	pub fn get(&self, i: usize) -> Option<&T> {
+		if i >= self.len() {
+			return None;
+		}
+		Some(&self.chunk(i / self.chunk_len)[i % self.chunk_len])
+	}
+
+	/// Returns the number of rows of the 2-D array.
+	#[inline]
+	pub fn rows(&self) -> usize {
+		self.len() / self.cols
+	}
+
+	/// Returns the number of columns of the 2-D array.
+	#[inline]
+	pub const fn cols(&self) -> usize {
+		self.cols
+	}
+
+	/// Returns a reference to the element at `row` and `col` of the
+	/// 2-D array.
+	///
+	/// # Panics
+	///
+	/// If `row` or `col` is out of bounds.
+	pub fn at(&self, row: usize, col: usize) -> &T {
+		assert!(col < self.cols, "column out of bounds");
+		self.get(row * self.cols + col).expect("row out of bounds")
+	}
+
+	/// Returns an iterator over row `row` of the 2-D array.
+	#[inline]
+	pub fn row(&self, row: usize) -> impl Iterator<Item = &T> + '_ {
+		(0..self.cols).map(move |col| self.at(row, col))
+	}
+
+	/// Returns an iterator over column `col` of the 2-D array.
+	#[inline]
+	pub fn column(
+		&self,
+		col: usize,
+	) -> impl Iterator<Item = &T> + '_ {
+		(0..self.rows()).map(move |row| self.at(row, col))
+	}
+
+	/// Copy the elements starting at `offset` into `buf`.
+	///
+	/// # Panics
+	///
+	/// If the range to read is out of bounds.
+	pub fn read(&self, mut offset: usize, mut buf: &mut [T])
+	where
+		T: Clone,
+	{
+		assert!(
+			offset
+				.checked_add(buf.len())
+				.is_some_and(|end| end <= self.len()),
+			"read out of bounds"
+		);
+		while !buf.is_empty() {
+			let i = offset / self.chunk_len;
+			let start = offset % self.chunk_len;
+			let chunk = &self.chunk(i)[start..];
+			let n = chunk.len().min(buf.len());
+			let (head, tail) = buf.split_at_mut(n);
+			head.clone_from_slice(&chunk[..n]);
+			buf = tail;
+			offset += n;
+		}
+	}
+
+	/// Write the dirty chunks into the originating [`CowCell`],
+	/// handing back the borrow in [`CommitError::Busy`] if other
+	/// borrows are reading the cell.
+	///
+	/// As this borrow reads the original value for as long as it is
+	/// alive, the cell cannot have changed since it was made, so this
+	/// never fails with [`CommitError::Conflict`].
+	pub fn commit(mut self) -> Result<(), CommitError<Self>>
+	where
+		S: AsMut<[T]>,
+	{
+		if self.copies.is_empty() {
+			return Ok(());
+		}
+		let (chunk_len, copies) = (self.chunk_len, &mut self.copies);
+		let done = self.ptr.commit_in_place(
+			&mut self.read,
+			|buf, displaced: &mut Vec<_>| {
+				let buf = buf.as_mut();
+				for (i, mut chunk) in core::mem::take(copies) {
+					let start = i * chunk_len;
+					let end = start + chunk.len();
+					buf[start..end].swap_with_slice(&mut chunk);
+					displaced.push(chunk);
+				}
+			},
+		);
+		if !done {
+			return Err(CommitError::Busy(self));
+		}
+		Ok(())
+	}
+}
+
+impl<'a, T: Clone, S: ?Sized + AsRef<[T]>> CowChunks<'a, T, S> {
+	/// Get a mutable reference to chunk `i`, copying it if necessary.
+	///
+	/// # Panics
+	///
+	/// If `i` is out of bounds.
+	pub fn chunk_mut(&mut self, i: usize) -> &mut [T] {
+		assert!(i < self.chunk_count(), "chunk out of bounds");
+		if !self.is_dirty(i) {
+			let chunk = self.base()[self.range(i)].into();
+			self.copies.insert(i, chunk);
+			self.dirty[i / BITS] |= 1 << (i % BITS);
+		}
+		self.copies.get_mut(&i).unwrap()
+	}
+
+	/// Returns a mutable reference to the element at index `i`,
+	/// copying its chunk if necessary.
+	#[inline]
+	pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+		if i >= self.len() {
+			return None;
+		}
+		let chunk_len = self.chunk_len;
+		Some(&mut self.chunk_mut(i / chunk_len)[i % chunk_len])
+	}
+
+	/// Returns a mutable reference to the element at `row` and `col`
+	/// of the 2-D array, copying its chunk if necessary.
+	///
+	/// # Panics
+	///
+	/// If `row` or `col` is out of bounds.
+	pub fn at_mut(&mut self, row: usize, col: usize) -> &mut T {
+		assert!(col < self.cols, "column out of bounds");
+		self.get_mut(row * self.cols + col)
+			.expect("row out of bounds")
+	}
+
+	/// Copy `data` into the slice starting at `offset`, copying the
+	/// chunks it spans.
+	///
+	/// # Panics
+	///
+	/// If the range to write is out of bounds.
+	pub fn write(&mut self, mut offset: usize, mut data: &[T]) {
+		assert!(
+			offset
+				.checked_add(data.len())
+				.is_some_and(|end| end <= self.len()),
+			"write out of bounds"
+		);
+		while !data.is_empty() {
+			let i = offset / self.chunk_len;
+			let start = offset % self.chunk_len;
+			let chunk = &mut self.chunk_mut(i)[start..];
+			let n = chunk.len().min(data.len());
+			chunk[..n].clone_from_slice(&data[..n]);
+			data = &data[n..];
+			offset += n;
+		}
+	}
+
+	/// Consume the [`CowChunks`], flattening the chunks into an owned
+	/// vector.
+	pub fn into_inner(self) -> Vec<T> {
+		let mut buf = self.base().to_vec();
+		for (&i, chunk) in &self.copies {
+			buf[self.range(i)].clone_from_slice(chunk);
+		}
+		buf
+	}
+}
+
+impl<T, S: ?Sized + AsRef<[T]>> fmt::Debug for CowChunks<'_, T, S> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("CowChunks")
+			.field("len", &self.len())
+			.field("chunk_len", &self.chunk_len)
+			.field("dirty", &self.copies.len())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn grid() {
+		let cell = CowCell::new((0..12).collect::<Vec<u32>>());
+		let mut grid = cell.chunks(4).with_cols(3);
+		assert_eq!((grid.rows(), grid.cols()), (4, 3));
+		assert_eq!(
+			grid.column(2).collect::<Vec<_>>(),
+			[&2, &5, &8, &11]
+		);
+		*grid.at_mut(1, 1) = 0;
+		assert!(grid.is_dirty(1));
+		assert_eq!(grid.row(1).collect::<Vec<_>>(), [&3, &0, &5]);
+		assert_eq!(grid.get(12), None);
+		assert_eq!(grid.into_inner()[3..6], [3, 0, 5]);
+	}
+
+	#[test]
+	#[should_panic = "not a multiple"]
+	fn ragged_rows() {
+		let cell = CowCell::new(vec![0; 10]);
+		cell.chunks(4).with_cols(3);
+	}
+}
@@ -0,0 +1,480 @@
+//! Structured changes between two values, and patches replaying
+//! them.
+
+use crate::{CowCell, CowRef, DiffConflict, PatchConflict};
+use core::borrow::Borrow;
+use core::fmt;
+
+/// A type whose values can be compared into a structured change set,
+/// which can then be applied to other values.
+///
+/// This can be implemented for structs with `#[derive(Diff)]`, behind
+/// the `derive` feature, which generates a `<Name>Changes` struct
+/// holding an `Option` with the changes of every field.
+pub trait Diff {
+	/// The changes that turn one value into another.
+	type Changes;
+
+	/// Returns the changes that turn `self` into `other`, or [`None`]
+	/// if both are equal.
+	fn diff(&self, other: &Self) -> Option<Self::Changes>;
+
+	/// Apply changes returned by [`Diff::diff`] to `self`. On
+	/// conflict, `self` may be left partially modified.
+	fn apply(
+		&mut self,
+		changes: &Self::Changes,
+	) -> Result<(), PatchConflict>;
+}
+
+impl<'a, T: Diff> CowRef<'a, T> {
+	/// Returns the changes made by the copy of this borrow, compared
+	/// to the value it was copied from, or [`None`] if no copy was
+	/// made or it is equal to that value.
+	///
+	/// The original value is read from the originating [`CowCell`],
+	/// so this fails with [`DiffConflict`] if the cell was committed
+	/// to since this borrow was made.
+	///
+	/// ```rust
+	/// # #[cfg(feature = "alloc")] {
+	/// use cowcell::CowCell;
+	/// use cowcell::diff::VecChange;
+	///
+	/// let cell = CowCell::new(vec![1, 2, 3]);
+	/// let mut borrow = cell.borrow();
+	/// borrow[0] = 4;
+	/// borrow.truncate(2);
+	/// assert_eq!(
+	///     borrow.diff(),
+	///     Ok(Some(vec![VecChange::Changed(0, 4), VecChange::Truncated(2)])),
+	/// );
+	/// # }
+	/// ```
+	pub fn diff(&self) -> Result<Option<T::Changes>, DiffConflict> {
+		let Some(copy) = self.copy.as_ref() else {
+			return Ok(None);
+		};
+		let _read = self.ptr.state.read();
+		if self.ptr.version() != self.version {
+			return Err(DiffConflict);
+		}
+		// SAFETY: we hold a read on the cell.
+		let orig = unsafe { self.ptr.get_unchecked() };
+		Ok(orig.diff(copy.borrow()))
+	}
+
+	/// Record the changes made by this borrow as a [`Patch`], which
+	/// can be replayed on other cells. This fails as
+	/// [`CowRef::diff`] does.
+	#[inline]
+	pub fn patch(&self) -> Result<Patch<T>, DiffConflict> {
+		self.diff().map(Patch::new)
+	}
+}
+
+/// Changes recorded from a [`CowRef`] with [`CowRef::patch`], to be
+/// replayed on other [`CowCell`]s.
+///
+/// ```rust
+/// # #[cfg(feature = "alloc")] {
+/// use cowcell::CowCell;
+///
+/// let cell = CowCell::new(vec![1, 2]);
+/// let mut borrow = cell.borrow();
+/// borrow.push(3);
+/// let patch = borrow.patch().unwrap();
+///
+/// // Replay the change on a newer version of the value.
+/// let replica = CowCell::new(vec![0, 2]);
+/// let patched = patch.apply(&replica).unwrap();
+/// assert_eq!(*patched, [0, 2, 3]);
+/// assert_eq!(*replica.borrow(), [0, 2]);
+/// # }
+/// ```
+pub struct Patch<T: Diff> {
+	changes: Option<T::Changes>,
+}
+
+impl<T: Diff> Patch<T> {
+	/// Create a patch from changes returned by [`Diff::diff`].
+	#[inline]
+	pub const fn new(changes: Option<T::Changes>) -> Self {
+		Self { changes }
+	}
+
+	/// Returns [`true`] if this patch changes nothing.
+	#[inline]
+	pub const fn is_empty(&self) -> bool {
+		self.changes.is_none()
+	}
+
+	/// Returns the changes in this patch.
+	#[inline]
+	pub const fn changes(&self) -> Option<&T::Changes> {
+		self.changes.as_ref()
+	}
+
+	/// Consume the patch, retrieving its changes.
+	#[inline]
+	pub fn into_changes(self) -> Option<T::Changes> {
+		self.changes
+	}
+}
+
+impl<T: Diff + Clone> Patch<T> {
+	/// Apply this patch to the value in `cell`, returning a borrow
+	/// whose copy holds the patched value. The cell itself is left
+	/// untouched, and the borrow can be committed into it.
+	///
+	/// An empty patch returns a borrow without a copy.
+	pub fn apply<'a>(
+		&self,
+		cell: &'a CowCell<T>,
+	) -> Result<CowRef<'a, T>, PatchConflict> {
+		let mut borrow = cell.borrow();
+		if let Some(changes) = self.changes.as_ref() {
+			borrow.get_mut().apply(changes)?;
+		}
+		Ok(borrow)
+	}
+}
+
+impl<T: Diff> Clone for Patch<T>
+where
+	T::Changes: Clone,
+{
+	#[inline]
+	fn clone(&self) -> Self {
+		Self::new(self.changes.clone())
+	}
+}
+
+impl<T: Diff> fmt::Debug for Patch<T>
+where
+	T::Changes: fmt::Debug,
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Patch")
+			.field("changes", &self.changes)
+			.finish()
+	}
+}
+
+macro_rules! impl_diff_by_value {
+	($($t:ty),* $(,)?) => {$(
+		impl Diff for $t {
+			type Changes = Self;
+
+			#[inline]
+			fn diff(&self, other: &Self) -> Option<Self> {
+				(self != other).then(|| other.clone())
+			}
+
+			#[inline]
+			fn apply(
+				&mut self,
+				changes: &Self,
+			) -> Result<(), PatchConflict> {
+				self.clone_from(changes);
+				Ok(())
+			}
+		}
+	)*};
+}
+
+impl_diff_by_value!(
+	(),
+	bool,
+	char,
+	u8,
+	u16,
+	u32,
+	u64,
+	u128,
+	usize,
+	i8,
+	i16,
+	i32,
+	i64,
+	i128,
+	isize,
+	f32,
+	f64,
+);
+
+#[cfg(feature = "alloc")]
+impl_diff_by_value!(alloc::string::String);
+
+/// A change to an [`Option`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionChange<T: Diff> {
+	/// The value was set from [`None`].
+	Inserted(T),
+	/// The value was set to [`None`].
+	Removed,
+	/// The inner value changed.
+	Changed(T::Changes),
+}
+
+impl<T: Diff + Clone> Diff for Option<T> {
+	type Changes = OptionChange<T>;
+
+	fn diff(&self, other: &Self) -> Option<Self::Changes> {
+		match (self, other) {
+			(None, None) => None,
+			(None, Some(v)) => {
+				Some(OptionChange::Inserted(v.clone()))
+			}
+			(Some(_), None) => Some(OptionChange::Removed),
+			(Some(a), Some(b)) => {
+				a.diff(b).map(OptionChange::Changed)
+			}
+		}
+	}
+
+	fn apply(
+		&mut self,
+		changes: &Self::Changes,
+	) -> Result<(), PatchConflict> {
+		match (self.as_mut(), changes) {
+			(None, OptionChange::Inserted(v)) => {
+				*self = Some(v.clone())
+			}
+			(Some(_), OptionChange::Removed) => *self = None,
+			(Some(v), OptionChange::Changed(c)) => v.apply(c)?,
+			_ => return Err(PatchConflict),
+		}
+		Ok(())
+	}
+}
+
+#[cfg(feature = "alloc")]
+pub use collections::{MapChange, VecChange};
+
+#[cfg(feature = "alloc")]
+mod collections {
+	use super::Diff;
+	use crate::PatchConflict;
+	use alloc::collections::BTreeMap;
+	use alloc::vec::Vec;
+
+	/// A change to a [`Vec`]. Changes are listed in the order they
+	/// must be applied.
+	#[derive(Debug, Clone, PartialEq)]
+	pub enum VecChange<T: Diff> {
+		/// The element at the given index changed.
+		Changed(usize, T::Changes),
+		/// The vector was truncated to the given length.
+		Truncated(usize),
+		/// An element was appended.
+		Pushed(T),
+	}
+
+	impl<T: Diff + Clone> Diff for Vec<T> {
+		type Changes = Vec<VecChange<T>>;
+
+		fn diff(&self, other: &Self) -> Option<Self::Changes> {
+			let mut changes = Vec::new();
+			for (i, (a, b)) in self.iter().zip(other).enumerate() {
+				if let Some(c) = a.diff(b) {
+					changes.push(VecChange::Changed(i, c));
+				}
+			}
+			if other.len() < self.len() {
+				changes.push(VecChange::Truncated(other.len()));
+			}
+			let tail = other.iter().skip(self.len());
+			changes.extend(tail.cloned().map(VecChange::Pushed));
+			(!changes.is_empty()).then_some(changes)
+		}
+
+		fn apply(
+			&mut self,
+			changes: &Self::Changes,
+		) -> Result<(), PatchConflict> {
+			for change in changes {
+				match change {
+					VecChange::Changed(i, c) => self
+						.get_mut(*i)
+						.ok_or(PatchConflict)?
+						.apply(c)?,
+					VecChange::Truncated(len) => {
+						if *len > self.len() {
+							return Err(PatchConflict);
+						}
+						self.truncate(*len);
+					}
+					VecChange::Pushed(v) => self.push(v.clone()),
+				}
+			}
+			Ok(())
+		}
+	}
+
+	/// A change to a map.
+	#[derive(Debug, Clone, PartialEq)]
+	pub enum MapChange<K, V: Diff> {
+		/// A new entry was inserted.
+		Inserted(K, V),
+		/// An entry was removed.
+		Removed(K),
+		/// The value of an entry changed.
+		Changed(K, V::Changes),
+	}
+
+	macro_rules! diff_maps {
+		($a:expr, $b:expr) => {{
+			let (a, b) = ($a, $b);
+			let mut changes = Vec::new();
+			for (k, v) in a.iter() {
+				match b.get(k) {
+					None => {
+						changes.push(MapChange::Removed(k.clone()))
+					}
+					Some(w) => {
+						if let Some(c) = v.diff(w) {
+							changes.push(MapChange::Changed(
+								k.clone(),
+								c,
+							));
+						}
+					}
+				}
+			}
+			for (k, v) in b.iter() {
+				if !a.contains_key(k) {
+					changes.push(MapChange::Inserted(
+						k.clone(),
+						v.clone(),
+					));
+				}
+			}
+			(!changes.is_empty()).then_some(changes)
+		}};
+	}
+
+	macro_rules! apply_maps {
+		($map:expr, $changes:expr) => {{
+			let map = $map;
+			for change in $changes {
+				match change {
+					MapChange::Inserted(k, v) => {
+						if map.contains_key(k) {
+							return Err(PatchConflict);
+						}
+						map.insert(k.clone(), v.clone());
+					}
+					MapChange::Removed(k) => {
+						map.remove(k).ok_or(PatchConflict)?;
+					}
+					MapChange::Changed(k, c) => map
+						.get_mut(k)
+						.ok_or(PatchConflict)?
+						.apply(c)?,
+				}
+			}
+			Ok(())
+		}};
+	}
+
+	impl<K: Ord + Clone, V: Diff + Clone> Diff for BTreeMap<K, V> {
+		type Changes = Vec<MapChange<K, V>>;
+
+		fn diff(&self, other: &Self) -> Option<Self::Changes> {
+			diff_maps!(self, other)
+		}
+
+		fn apply(
+			&mut self,
+			changes: &Self::Changes,
+		) -> Result<(), PatchConflict> {
+			apply_maps!(self, changes)
+		}
+	}
+
+	#[cfg(feature = "std")]
+	impl<K, V, S> Diff for std::collections::HashMap<K, V, S>
+	where
+		K: Eq + core::hash::Hash + Clone,
+		V: Diff + Clone,
+		S: core::hash::BuildHasher,
+	{
+		type Changes = Vec<MapChange<K, V>>;
+
+		fn diff(&self, other: &Self) -> Option<Self::Changes> {
+			diff_maps!(self, other)
+		}
+
+		fn apply(
+			&mut self,
+			changes: &Self::Changes,
+		) -> Result<(), PatchConflict> {
+			apply_maps!(self, changes)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::CowCell;
+	#[cfg(feature = "alloc")]
+	use std::collections::BTreeMap;
+
+	#[test]
+	#[cfg(feature = "alloc")]
+	fn diff_map() {
+		let cell = CowCell::new(BTreeMap::from([(1, 1), (2, 2)]));
+		let mut borrow = cell.borrow();
+		assert_eq!(borrow.diff(), Ok(None));
+		borrow.remove(&1);
+		borrow.insert(2, 3);
+		borrow.insert(4, 4);
+		assert_eq!(
+			borrow.diff().unwrap().unwrap(),
+			[
+				MapChange::Removed(1),
+				MapChange::Changed(2, 3),
+				MapChange::Inserted(4, 4),
+			]
+		);
+	}
+
+	#[test]
+	fn diff_option() {
+		let cell = CowCell::new(Some(1));
+		let mut borrow = cell.borrow();
+		*borrow = None;
+		assert_eq!(borrow.diff(), Ok(Some(OptionChange::Removed)));
+		*borrow = Some(1);
+		assert_eq!(borrow.diff(), Ok(None));
+	}
+
+	#[test]
+	#[cfg(feature = "alloc")]
+	fn diff_outdated() {
+		let cell = CowCell::new(vec![1]);
+		let mut a = cell.borrow();
+		a.get_mut();
+		let mut b = cell.borrow();
+		b.push(2);
+		b.commit().unwrap();
+		assert_eq!(a.diff(), Err(DiffConflict));
+		assert!(a.patch().is_err());
+	}
+
+	#[test]
+	#[cfg(feature = "alloc")]
+	fn patch_conflict() {
+		let cell = CowCell::new(BTreeMap::from([(1, 1)]));
+		let mut borrow = cell.borrow();
+		borrow.remove(&1);
+		let patch = borrow.patch().unwrap();
+
+		let replica = CowCell::new(BTreeMap::from([(2, 2)]));
+		assert_eq!(patch.apply(&replica).unwrap_err(), PatchConflict);
+		let replica = CowCell::new(BTreeMap::from([(1, 3)]));
+		assert!(patch.apply(&replica).unwrap().is_empty());
+	}
+}
@@ -0,0 +1,124 @@
+//! Errors returned when committing a borrow, speculating, diffing or
+//! applying a patch, or failing to allocate a copy.
+
+use core::fmt;
+
+/// The reason a commit was rejected, along with the rejected copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitError<T> {
+	/// Other borrows are still reading the original value, so it
+	/// cannot be replaced.
+	Busy(T),
+	/// The cell was changed after the borrow was made. Committing
+	/// would overwrite the newer value.
+	Conflict(T),
+}
+
+impl<T> CommitError<T> {
+	/// Consume the error, retrieving the rejected copy.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		match self {
+			Self::Busy(v) | Self::Conflict(v) => v,
+		}
+	}
+
+	/// Returns [`true`] if the commit was rejected because of a
+	/// conflicting commit.
+	#[inline]
+	pub const fn is_conflict(&self) -> bool {
+		matches!(self, Self::Conflict(_))
+	}
+}
+
+impl<T> fmt::Display for CommitError<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Busy(_) => f.write_str("cell is being read"),
+			Self::Conflict(_) => {
+				f.write_str("cell was changed after the borrow")
+			}
+		}
+	}
+}
+
+impl<T: fmt::Debug> core::error::Error for CommitError<T> {}
+
+/// The reason a speculative mutation was not committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeculateError<T, E> {
+	/// The closure returned an error, and its copy was dropped.
+	Aborted(E),
+	/// The closure succeeded, but its copy could not be committed.
+	Commit(CommitError<T>),
+}
+
+impl<T, E> From<CommitError<T>> for SpeculateError<T, E> {
+	#[inline]
+	fn from(err: CommitError<T>) -> Self {
+		Self::Commit(err)
+	}
+}
+
+impl<T, E: fmt::Display> fmt::Display for SpeculateError<T, E> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Aborted(e) => write!(f, "speculation aborted: {e}"),
+			Self::Commit(e) => {
+				write!(f, "speculation not committed: {e}")
+			}
+		}
+	}
+}
+
+impl<T: fmt::Debug, E: core::error::Error> core::error::Error
+	for SpeculateError<T, E>
+{
+}
+
+/// A patch that does not apply cleanly, because the value it is
+/// applied to does not have what the patch changes, such as a removed
+/// map entry or an index past the end of a vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchConflict;
+
+impl fmt::Display for PatchConflict {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("patch does not apply cleanly")
+	}
+}
+
+impl core::error::Error for PatchConflict {}
+
+/// A diff that cannot be computed, because the cell was committed to
+/// after the borrow was made, so the value the borrow copied is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffConflict;
+
+impl fmt::Display for DiffConflict {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("cell was changed after the borrow")
+	}
+}
+
+impl core::error::Error for DiffConflict {}
+
+/// A copy that could not be made because an allocation failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError;
+
+#[cfg(feature = "alloc")]
+impl From<alloc::collections::TryReserveError> for AllocError {
+	#[inline]
+	fn from(_: alloc::collections::TryReserveError) -> Self {
+		Self
+	}
+}
+
+impl fmt::Display for AllocError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("memory allocation failed")
+	}
+}
+
+impl core::error::Error for AllocError {}
@@ -0,0 +1,105 @@
+//! Per-field clone-on-write views of a borrow.
+
+use crate::CowRef;
+use core::ops::{Deref, DerefMut};
+
+/// A type that can be viewed as a set of independently clone-on-write
+/// fields.
+///
+/// This is usually implemented with `#[derive(CowFields)]`, behind
+/// the `derive` feature, which generates a `<Name>Fields` struct
+/// holding a [`CowField`] for every field of `<Name>`. The generated
+/// struct has an `into_inner` method that assembles an owned value
+/// from the modified fields, cloning the untouched ones.
+pub trait CowFields {
+	/// The view over the fields of `Self`.
+	type Fields<'a>
+	where
+		Self: 'a;
+
+	/// Create a view over the fields of `self`, with nothing cloned.
+	fn fields(&self) -> Self::Fields<'_>;
+}
+
+impl<'a, T: CowFields> CowRef<'a, T> {
+	/// Create a view over the fields of the inner value, in which
+	/// every field is cloned independently on mutable access.
+	#[inline]
+	pub fn fields(&self) -> T::Fields<'_> {
+		self.get_ref().fields()
+	}
+}
+
+/// A single field with clone-on-write semantics on mutable access.
+///
+/// This type provides zero-cost immutable access to a borrowed value,
+/// and clones it into a private copy when accessed mutably.
+#[derive(Debug)]
+pub struct CowField<'a, T> {
+	ptr: &'a T,
+	copy: Option<T>,
+}
+
+impl<'a, T> CowField<'a, T> {
+	/// A new field borrowing the given value.
+	#[inline]
+	pub const fn new(ptr: &'a T) -> Self {
+		Self { ptr, copy: None }
+	}
+
+	/// Get an immutable reference to the inner value.
+	#[inline]
+	pub const fn get_ref(&self) -> &T {
+		match self.copy.as_ref() {
+			Some(v) => v,
+			None => self.ptr,
+		}
+	}
+
+	/// Returns [`true`] if this [`CowField`] has made a copy of the
+	/// original value.
+	#[inline]
+	pub const fn is_cloned(&self) -> bool {
+		self.copy.is_some()
+	}
+}
+
+impl<'a, T: Clone> CowField<'a, T> {
+	/// Get a mutable reference to the inner value, cloning the
+	/// original value if necessary.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		let ptr = self.ptr;
+		self.copy.get_or_insert_with(|| ptr.clone())
+	}
+
+	/// Consume the [`CowField`], retrieving the inner value. This
+	/// clones the original value if a copy was not already made.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.copy.unwrap_or_else(|| self.ptr.clone())
+	}
+}
+
+impl<'a, T> From<&'a T> for CowField<'a, T> {
+	#[inline]
+	fn from(ptr: &'a T) -> Self {
+		Self::new(ptr)
+	}
+}
+
+impl<T> Deref for CowField<'_, T> {
+	type Target = T;
+
+	#[inline]
+	fn deref(&self) -> &Self::Target {
+		self.get_ref()
+	}
+}
+
+impl<T: Clone> DerefMut for CowField<'_, T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.get_mut()
+	}
+}
@@ -0,0 +1,191 @@
+//! Forking a [`CowCell`] into borrows mutated on scoped threads, or
+//! on the `rayon` thread pool with the `rayon` feature.
+
+use crate::{CowCell, CowRef};
+use core::cmp::Ordering;
+use std::thread;
+use std::vec::Vec;
+
+/// Forking the value into independent borrows, each mutated on its own
+/// scoped thread.
+///
+/// The borrows are created on the worker threads and handed back to
+/// the caller, so both `&CowCell<T>` and `CowRef<'_, T>` must cross
+/// threads. The cell is [`Sync`] and the borrow is [`Send`] when
+/// `T: Send + Sync`, which is what these methods require:
+///
+/// ```compile_fail
+/// use cowcell::CowCell;
+/// use std::cell::Cell;
+///
+/// // `Cell` is not `Sync`, so borrows cannot be shared across threads.
+/// let cell = CowCell::new(Cell::new(0));
+/// cell.fork_scope(2, |_, _| ());
+/// ```
+impl<T: Clone + Send + Sync> CowCell<T> {
+	/// Run `work` on `n` scoped threads, each with its own borrow of
+	/// the value and its index, and return every borrow along with
+	/// the result of its worker, in index order.
+	///
+	/// # Panics
+	///
+	/// If a worker panics, the panic is propagated once all of them
+	/// are done.
+	pub fn fork_scope<R, F>(
+		&self,
+		n: usize,
+		work: F,
+	) -> Vec<(CowRef<'_, T>, R)>
+	where
+		R: Send,
+		F: Fn(usize, &mut CowRef<'_, T>) -> R + Sync,
+	{
+		let work = &work;
+		thread::scope(|s| {
+			let workers = (0..n)
+				.map(|i| {
+					s.spawn(move || {
+						let mut borrow = self.borrow();
+						let res = work(i, &mut borrow);
+						(borrow, res)
+					})
+				})
+				.collect::<Vec<_>>();
+			workers
+				.into_iter()
+				.map(|w| {
+					w.join().unwrap_or_else(|e| {
+						std::panic::resume_unwind(e)
+					})
+				})
+				.collect()
+		})
+	}
+
+	/// Like [`CowCell::fork_scope`], but run the workers as tasks of
+	/// a [`rayon::scope`] on the current `rayon` thread pool instead
+	/// of spawning a thread for each of them.
+	///
+	/// # Panics
+	///
+	/// If a worker panics, the panic is propagated once all of them
+	/// are done.
+	///
+	/// ```rust
+	/// use cowcell::CowCell;
+	///
+	/// let cell = CowCell::new(vec![0; 4]);
+	/// let forks = cell.par_fork(8, |i, v| v[i % 4] = i);
+	/// assert_eq!(forks.len(), 8);
+	/// assert_eq!(*forks[5].0, [0, 5, 0, 0]);
+	/// ```
+	#[cfg(feature = "rayon")]
+	pub fn par_fork<R, F>(
+		&self,
+		n: usize,
+		work: F,
+	) -> Vec<(CowRef<'_, T>, R)>
+	where
+		R: Send,
+		F: Fn(usize, &mut CowRef<'_, T>) -> R + Sync,
+	{
+		let work = &work;
+		let mut forks = (0..n).map(|_| None).collect::<Vec<_>>();
+		rayon::scope(|s| {
+			for (i, fork) in forks.iter_mut().enumerate() {
+				s.spawn(move |_| {
+					let mut borrow = self.borrow();
+					let res = work(i, &mut borrow);
+					*fork = Some((borrow, res));
+				});
+			}
+		});
+		forks.into_iter().map(Option::unwrap).collect()
+	}
+
+	/// Like [`CowCell::fork_scope`], but only keep the borrow whose
+	/// result is the greatest according to `compare`. Returns
+	/// [`None`] if `n` is zero.
+	///
+	/// ```rust
+	/// use cowcell::CowCell;
+	///
+	/// let cell = CowCell::new(vec![3, 1, 2]);
+	/// let (best, score) = cell
+	///     .fork_best_by(
+	///         3,
+	///         |i, v| {
+	///             v.rotate_left(i);
+	///             v[0]
+	///         },
+	///         |a, b| a.cmp(b),
+	///     )
+	///     .unwrap();
+	/// assert_eq!(score, 3);
+	/// assert_eq!(*best, [3, 1, 2]);
+	/// best.commit().unwrap();
+	/// ```
+	pub fn fork_best_by<R, F, C>(
+		&self,
+		n: usize,
+		work: F,
+		mut compare: C,
+	) -> Option<(CowRef<'_, T>, R)>
+	where
+		R: Send,
+		F: Fn(usize, &mut CowRef<'_, T>) -> R + Sync,
+		C: FnMut(&R, &R) -> Ordering,
+	{
+		self.fork_scope(n, work)
+			.into_iter()
+			.max_by(|(_, a), (_, b)| compare(a, b))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	fn assert_send<T: Send>() {}
+	fn assert_sync<T: Sync>() {}
+
+	#[test]
+	fn bounds() {
+		assert_send::<CowCell<Vec<u8>>>();
+		assert_sync::<CowCell<Vec<u8>>>();
+		assert_send::<CowRef<'_, Vec<u8>>>();
+		assert_sync::<CowRef<'_, Vec<u8>>>();
+		assert_send::<CowRef<'_, Arc<u8>>>();
+	}
+
+	#[test]
+	fn fork_scope() {
+		let cell = CowCell::new(0);
+		let forks = cell.fork_scope(4, |i, v| {
+			**v += i;
+			i % 2 == 0
+		});
+		let copies = forks
+			.iter()
+			.map(|(v, even)| (**v, *even))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			copies,
+			[(0, true), (1, false), (2, true), (3, false)]
+		);
+		assert_eq!(*cell.borrow(), 0);
+	}
+
+	#[test]
+	#[cfg(feature = "rayon")]
+	fn par_fork() {
+		let cell = CowCell::new(0);
+		let forks = cell.par_fork(16, |i, v| {
+			**v += i;
+			i
+		});
+		assert!(forks.iter().all(|(v, i)| **v == *i));
+		assert!(cell.par_fork(0, |_, _| ()).is_empty());
+	}
+}
@@ -0,0 +1,258 @@
+//! Undo and redo on top of a [`CowCell`].
+
+use crate::{CowCell, CowRef};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A [`CowCell`] recording every committed value as a revision, with
+/// undo and redo.
+///
+/// Each edit is a regular borrow of the current revision, which only
+/// clones the value when it is modified. Once the borrow is done, its
+/// copy is recorded as the new current revision.
+///
+/// By default every revision is kept. Memory can be bounded with
+/// [`CowHistory::with_max_revisions`] and
+/// [`CowHistory::with_byte_budget`], which evict the oldest revisions
+/// first.
+///
+/// ```rust
+/// use cowcell::CowHistory;
+///
+/// let mut doc = CowHistory::new(String::from("a"));
+/// doc.edit(|s| s.push('b'));
+/// doc.edit(|s| s.push('c'));
+/// assert_eq!(*doc.borrow(), "abc");
+///
+/// doc.undo();
+/// doc.undo();
+/// assert_eq!(*doc.borrow(), "a");
+/// doc.redo();
+/// assert_eq!(*doc.borrow(), "ab");
+///
+/// // Edits that do not modify the value record nothing.
+/// doc.edit(|s| assert_eq!(**s, "ab"));
+/// assert_eq!(doc.revision(), 1);
+/// ```
+pub struct CowHistory<T> {
+	cell: CowCell<T>,
+	/// Older revisions, the oldest first.
+	undo: VecDeque<T>,
+	/// Newer revisions, the newest first.
+	redo: Vec<T>,
+	/// Number of revisions evicted from `undo`.
+	evicted: usize,
+	max_revisions: Option<usize>,
+	budget: Option<Budget<T>>,
+	/// Size of the revisions in `undo` and `redo`, if there is a
+	/// budget.
+	bytes: usize,
+}
+
+/// A limit on the size of the stored revisions.
+struct Budget<T> {
+	max: usize,
+	size: fn(&T) -> usize,
+}
+
+impl<T> CowHistory<T> {
+	/// Create a new [`CowHistory`] whose first revision is the given
+	/// value.
+	#[inline]
+	pub const fn new(val: T) -> Self {
+		Self {
+			cell: CowCell::new(val),
+			undo: VecDeque::new(),
+			redo: Vec::new(),
+			evicted: 0,
+			max_revisions: None,
+			budget: None,
+			bytes: 0,
+		}
+	}
+
+	/// Keep at most `max` revisions besides the current one.
+	pub fn with_max_revisions(mut self, max: usize) -> Self {
+		self.max_revisions = Some(max);
+		self.evict();
+		self
+	}
+
+	/// Keep the revisions besides the current one within `bytes`, as
+	/// measured by `size`. The budget is enforced when recording new
+	/// revisions.
+	pub fn with_byte_budget(
+		mut self,
+		bytes: usize,
+		size: fn(&T) -> usize,
+	) -> Self {
+		self.budget = Some(Budget { max: bytes, size });
+		self.bytes =
+			self.undo.iter().chain(&self.redo).map(size).sum();
+		self.evict();
+		self
+	}
+
+	/// Returns the [`CowCell`] holding the current revision.
+	#[inline]
+	pub const fn get_cell(&self) -> &CowCell<T> {
+		&self.cell
+	}
+
+	/// Create a new borrow of the current revision.
+	#[inline]
+	pub fn borrow(&self) -> CowRef<'_, T> {
+		self.cell.borrow()
+	}
+
+	/// Returns the number of the current revision. The first revision
+	/// is 0, and numbers are not reused after eviction.
+	#[inline]
+	pub fn revision(&self) -> usize {
+		self.evicted + self.undo.len()
+	}
+
+	/// Returns the range of revision numbers that can be reached.
+	#[inline]
+	pub fn revisions(&self) -> core::ops::RangeInclusive<usize> {
+		self.evicted..=self.revision() + self.redo.len()
+	}
+
+	/// Record `val` as the new current revision, discarding the
+	/// revisions that could be redone.
+	pub fn record(&mut self, val: T) {
+		let old = core::mem::replace(self.cell.get_mut(), val);
+		self.bytes += self.size(&old);
+		self.undo.push_back(old);
+		for rev in core::mem::take(&mut self.redo) {
+			self.bytes -= self.size(&rev);
+		}
+		self.evict();
+	}
+
+	/// Undo the current revision. Returns [`false`] if there is
+	/// nothing to undo.
+	pub fn undo(&mut self) -> bool {
+		let Some(prev) = self.undo.pop_back() else {
+			return false;
+		};
+		self.bytes -= self.size(&prev);
+		let cur = core::mem::replace(self.cell.get_mut(), prev);
+		self.bytes += self.size(&cur);
+		self.redo.push(cur);
+		true
+	}
+
+	/// Redo the last undone revision. Returns [`false`] if there is
+	/// nothing to redo.
+	pub fn redo(&mut self) -> bool {
+		let Some(next) = self.redo.pop() else {
+			return false;
+		};
+		self.bytes -= self.size(&next);
+		let cur = core::mem::replace(self.cell.get_mut(), next);
+		self.bytes += self.size(&cur);
+		self.undo.push_back(cur);
+		true
+	}
+
+	/// Undo or redo up to the given revision. Returns [`false`],
+	/// leaving the history untouched, if the revision cannot be
+	/// reached.
+	pub fn jump_to(&mut self, revision: usize) -> bool {
+		if !self.revisions().contains(&revision) {
+			return false;
+		}
+		while self.revision() > revision {
+			self.undo();
+		}
+		while self.revision() < revision {
+			self.redo();
+		}
+		true
+	}
+
+	/// Consume the [`CowHistory`], retrieving the current revision.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.cell.into_inner()
+	}
+
+	fn size(&self, val: &T) -> usize {
+		self.budget.as_ref().map_or(0, |b| (b.size)(val))
+	}
+
+	/// Drop the oldest revisions until the limits are met.
+	fn evict(&mut self) {
+		let max = self.max_revisions.unwrap_or(usize::MAX);
+		let budget =
+			self.budget.as_ref().map_or(usize::MAX, |b| b.max);
+		while self.undo.len() + self.redo.len() > max
+			|| self.bytes > budget
+		{
+			let Some(old) = self.undo.pop_front() else {
+				break;
+			};
+			self.bytes -= self.size(&old);
+			self.evicted += 1;
+		}
+	}
+}
+
+impl<T: Clone> CowHistory<T> {
+	/// Run an edit session on a borrow of the current revision. If
+	/// the borrow made a copy, it is recorded as the new current
+	/// revision.
+	pub fn edit<F: FnOnce(&mut CowRef<'_, T>)>(&mut self, f: F) {
+		let mut borrow = self.cell.borrow();
+		f(&mut borrow);
+		if borrow.is_cloned() {
+			let val = borrow.into_inner();
+			self.record(val);
+		}
+	}
+}
+
+impl<T: fmt::Debug> fmt::Debug for CowHistory<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("CowHistory")
+			.field("cell", &self.cell)
+			.field("undo", &self.undo)
+			.field("redo", &self.redo)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn max_revisions() {
+		let mut hist = CowHistory::new(0).with_max_revisions(2);
+		for i in 1..=4 {
+			hist.record(i);
+		}
+		assert_eq!(hist.revisions(), 2..=4);
+		assert!(!hist.jump_to(1));
+		assert!(hist.jump_to(2));
+		assert_eq!(*hist.borrow(), 2);
+		assert!(!hist.undo());
+	}
+
+	#[test]
+	fn byte_budget() {
+		let mut hist = CowHistory::new(vec![0u8; 10])
+			.with_byte_budget(25, |v| v.len());
+		hist.record(vec![1; 10]);
+		hist.record(vec![2; 10]);
+		hist.record(vec![3; 10]);
+		assert_eq!(hist.revisions(), 1..=3);
+		hist.undo();
+		hist.record(vec![4; 1]);
+		assert_eq!(hist.revisions(), 1..=3);
+		assert!(hist.jump_to(1));
+		assert_eq!(*hist.borrow(), [1; 10]);
+	}
+}
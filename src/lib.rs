@@ -18,45 +18,342 @@
 //! assert!(borrow.is_cloned());
 //!
 //! // The original value has not been modified.
-//! assert_eq!(*cell, 44);
+//! assert_eq!(*cell.borrow(), 44);
+//!
+//! // Until the copy is committed back into the cell.
+//! borrow.commit().unwrap();
+//! assert_eq!(*cell.borrow(), 45);
 //! ```
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod archive;
+#[cfg(feature = "alloc")]
+mod boxed;
+mod child;
+#[cfg(feature = "alloc")]
+mod chunks;
+pub mod diff;
+mod error;
+mod fields;
+#[cfg(feature = "std")]
+mod fork;
+#[cfg(feature = "alloc")]
+mod history;
+#[cfg(feature = "alloc")]
+mod map;
+#[cfg(feature = "alloc")]
+mod mvcc;
+#[cfg(feature = "alloc")]
+mod owned;
+#[cfg(feature = "alloc")]
+mod pages;
+#[cfg(feature = "alloc")]
+mod path;
+#[cfg(feature = "alloc")]
+mod pool;
+mod project;
+mod state;
+mod stm;
+#[cfg(feature = "alloc")]
+mod sync;
+mod to_copy;
+mod try_clone;
+#[cfg(feature = "alloc")]
+mod vec;
+mod view;
+
+#[cfg(feature = "rkyv")]
+pub use archive::CowRkyv;
+pub use archive::{ArchivedRef, CowArchived};
+pub use child::{ChildRef, CowLayer};
+#[cfg(feature = "alloc")]
+pub use chunks::CowChunks;
+use core::borrow::{self, BorrowMut};
+use core::cell::UnsafeCell;
+use core::cmp::Ordering;
+use core::fmt;
 use core::ops::{Deref, DerefMut};
+use core::panic::RefUnwindSafe;
+use core::sync::atomic::{self, AtomicUsize};
+#[cfg(feature = "derive")]
+pub use cowcell_derive::{CowFields, Diff};
+pub use diff::{Diff, Patch};
+pub use error::{
+	AllocError, CommitError, DiffConflict, PatchConflict,
+	SpeculateError,
+};
+pub use fields::{CowField, CowFields};
+#[cfg(feature = "alloc")]
+pub use history::CowHistory;
+#[cfg(feature = "alloc")]
+pub use map::{CowMap, OverlayLookup, OverlayMap};
+#[cfg(feature = "alloc")]
+pub use mvcc::{MvccCell, MvccRef};
+#[cfg(feature = "alloc")]
+pub use owned::CowOwned;
+#[cfg(feature = "alloc")]
+pub use pages::{CowPages, PAGE_SIZE};
+#[cfg(feature = "alloc")]
+pub use path::PathRef;
+#[cfg(feature = "alloc")]
+pub use pool::CowPool;
+pub use project::Projection;
+use state::{BorrowState, ReadGuard};
+pub use stm::{transact, TxCells};
+#[cfg(feature = "alloc")]
+pub use sync::{SyncCowCell, SyncCowRef};
+pub use to_copy::{
+	CowClone, CowCopy, DefaultClone, OwnedCopy, ToCopy,
+};
+pub use try_clone::TryClone;
+#[cfg(feature = "alloc")]
+pub use vec::CowVec;
+pub use view::CowView;
 
 /// A cell that can create borrows with clone-on-write semantics.
-#[derive(
-	Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord,
-)]
-#[repr(transparent)]
-pub struct CowCell<T> {
-	val: T,
+///
+/// The cell keeps track of the borrows that are reading its value,
+/// so that a [`CowRef`] can publish its copy back into the cell with
+/// [`CowRef::commit`] while other borrows are alive. Every commit
+/// bumps the version of the cell, which lets later commits detect
+/// that their copy is based on an outdated value.
+///
+/// The value may be unsized, such as a [`str`] or a slice, in which
+/// case borrows copy it into its owned counterpart (see [`ToCopy`]).
+/// With the `alloc` feature, boxed cells of such values can be built
+/// from references to them, e.g. `Box::<CowCell<str>>::from("abc")`.
+#[repr(C)]
+pub struct CowCell<T: ?Sized> {
+	state: BorrowState,
+	version: AtomicUsize,
+	val: UnsafeCell<T>,
 }
 
+// SAFETY: the value is only replaced while no borrow is reading it,
+// which `BorrowState` enforces across threads. Sharing the cell
+// shares `&T` between threads and moves values of `T` into it.
+unsafe impl<T: ?Sized + Send + Sync> Sync for CowCell<T> {}
+
+// A panic can only leave the value half-updated while a `CowMap`
+// commit applies its changes in place, if comparing or hashing a key
+// panics. The value is then a valid map, as a std map is after such a
+// panic in one of its own methods, and the version has been bumped,
+// so that the commits of older borrows conflict.
+impl<T: ?Sized + RefUnwindSafe> RefUnwindSafe for CowCell<T> {}
+
 impl<T> CowCell<T> {
 	/// Create a new [`CowCell`] containing the given value.
 	#[inline]
 	pub const fn new(val: T) -> Self {
-		Self { val }
+		Self {
+			state: BorrowState::new(),
+			version: AtomicUsize::new(0),
+			val: UnsafeCell::new(val),
+		}
+	}
+
+	/// Consume the [`CowCell`], retrieving the inner value.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.val.into_inner()
+	}
+}
+
+impl<T: ?Sized> CowCell<T> {
+	/// Returns the current version of the cell, which is bumped by
+	/// every successful commit.
+	#[inline]
+	pub fn version(&self) -> usize {
+		self.version.load(atomic::Ordering::Acquire)
+	}
+
+	/// Get a mutable reference to the inner value. No borrows can be
+	/// alive, so this needs no tracking.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		self.val.get_mut()
+	}
+
+	/// # Safety
+	///
+	/// The caller must hold a read on this cell for as long as the
+	/// returned reference is alive.
+	#[inline]
+	const unsafe fn get_unchecked(&self) -> &T {
+		&*self.val.get()
+	}
+
+	/// Run `f` on the value while holding a read on the cell.
+	#[inline]
+	fn with_ref<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+		let _read = self.state.read();
+		// SAFETY: we hold a read on the cell.
+		f(unsafe { self.get_unchecked() })
+	}
+
+	/// Modify the value in place with `f` through `read`, which an
+	/// overlay borrow holds for as long as it is alive. As the value
+	/// cannot have changed since the read was taken, this only fails,
+	/// returning [`false`], if other borrows are reading the cell.
+	///
+	/// `f` moves the values it replaces into its second argument,
+	/// which is dropped outside of the write, in case their
+	/// destructors use the cell. The version is bumped even if `f`
+	/// panics.
+	#[cfg(feature = "alloc")]
+	fn commit_in_place<D: Default>(
+		&self,
+		read: &mut ReadGuard<'_>,
+		f: impl FnOnce(&mut T, &mut D),
+	) -> bool {
+		let mut displaced = D::default();
+		let Some(write) = read.try_upgrade_in_place() else {
+			return false;
+		};
+		self.version.fetch_add(1, atomic::Ordering::Release);
+		// SAFETY: the write guard excludes every other access to
+		// the value.
+		f(unsafe { &mut *self.val.get() }, &mut displaced);
+		drop(write);
+		drop(displaced);
+		true
 	}
+}
 
+impl<T: ?Sized> CowCell<T> {
 	/// Create a new borrow with copy-on-write semantics.
 	#[inline]
-	pub const fn borrow(&self) -> CowRef<'_, T> {
-		CowRef::new(self)
+	pub fn borrow(&self) -> CowRef<'_, T>
+	where
+		T: OwnedCopy,
+	{
+		CowRef::new(self, DefaultClone)
 	}
 
-	/// Consume the [`CowCell`], retrieving the inner value.
+	/// Create a new borrow with copy-on-write semantics, which makes
+	/// its copy with the given [`CowClone`] strategy.
+	///
+	/// ```rust
+	/// use cowcell::CowCell;
+	/// use std::rc::Rc;
+	///
+	/// // Share the nodes of the list instead of deep cloning them.
+	/// let cell = CowCell::new(vec![Rc::new(vec![1]), Rc::new(vec![2])]);
+	/// let mut borrow =
+	///     cell.borrow_with(|v: &Vec<Rc<Vec<i32>>>| v.to_vec());
+	/// borrow.push(Rc::new(vec![3]));
+	/// assert!(Rc::ptr_eq(&borrow[0], &cell.borrow()[0]));
+	/// ```
 	#[inline]
-	pub fn into_inner(self) -> T {
-		self.val
+	pub fn borrow_with<C: CowClone<T>>(
+		&self,
+		cloner: C,
+	) -> CowRef<'_, T, C> {
+		CowRef::new(self, cloner)
 	}
 }
 
-impl<T> Deref for CowCell<T> {
-	type Target = T;
+impl<T: ToCopy<Owned = T>> CowCell<T> {
+	/// Try out a mutation on a private copy of the value, committing
+	/// it only if `f` succeeds.
+	///
+	/// If `f` returns an error or panics, its borrow is dropped along
+	/// with any copy, and the cell is left untouched. If it returns
+	/// `Ok`, the borrow is committed as with [`CowRef::commit`].
+	///
+	/// ```rust
+	/// use cowcell::{CowCell, SpeculateError};
+	///
+	/// let cell = CowCell::new(vec![1, 2]);
+	/// let res = cell.speculate(|v| {
+	///     v.push(3);
+	///     if v.len() > 2 { Err("too long") } else { Ok(()) }
+	/// });
+	/// assert_eq!(res, Err(SpeculateError::Aborted("too long")));
+	/// assert_eq!(*cell.borrow(), [1, 2]);
+	/// ```
+	pub fn speculate<R, E, F>(
+		&self,
+		f: F,
+	) -> Result<R, SpeculateError<T, E>>
+	where
+		F: FnOnce(&mut CowRef<'_, T>) -> Result<R, E>,
+	{
+		let mut borrow = self.borrow();
+		let res = f(&mut borrow).map_err(SpeculateError::Aborted)?;
+		borrow.commit()?;
+		Ok(res)
+	}
+}
 
-	fn deref(&self) -> &Self::Target {
-		&self.val
+impl<T: Clone> CowCell<T> {
+	/// Modify a copy of the value with `f` and commit it, running `f`
+	/// again on the newer value whenever the commit conflicts with
+	/// another one. `f` may thus be called more than once.
+	///
+	/// Commits rejected because other borrows are reading the value
+	/// are not retried, and return [`CommitError::Busy`].
+	pub fn update_with_retry<F: FnMut(&mut T)>(
+		&self,
+		mut f: F,
+	) -> Result<(), CommitError<T>> {
+		loop {
+			let mut borrow = self.borrow();
+			f(borrow.get_mut());
+			match borrow.commit() {
+				Err(CommitError::Conflict(_)) => continue,
+				res => return res,
+			}
+		}
+	}
+}
+
+impl<T: Clone> Clone for CowCell<T> {
+	#[inline]
+	fn clone(&self) -> Self {
+		Self::new(self.borrow().into_inner())
+	}
+}
+
+impl<T: Default> Default for CowCell<T> {
+	#[inline]
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for CowCell<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		self.with_ref(|val| {
+			f.debug_struct("CowCell").field("val", &val).finish()
+		})
+	}
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for CowCell<T> {
+	#[inline]
+	fn eq(&self, other: &Self) -> bool {
+		self.with_ref(|a| other.with_ref(|b| a == b))
+	}
+}
+
+impl<T: ?Sized + Eq> Eq for CowCell<T> {}
+
+impl<T: ?Sized + PartialOrd> PartialOrd for CowCell<T> {
+	#[inline]
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		self.with_ref(|a| other.with_ref(|b| a.partial_cmp(b)))
+	}
+}
+
+impl<T: ?Sized + Ord> Ord for CowCell<T> {
+	#[inline]
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.with_ref(|a| other.with_ref(|b| a.cmp(b)))
 	}
 }
 
@@ -73,17 +370,31 @@ impl<T> From<T> for CowCell<T> {
 /// value contained in a [`CowCell`]. When the inner type is accessed
 /// mutably, this type will clone the value, allowing the user to
 /// modify a private copy of `T`.
-#[derive(Debug)]
-pub struct CowRef<'a, T> {
+///
+/// The copy is made with the [`CowClone`] strategy `C`, which by
+/// default uses [`ToCopy`], so for an unsized `T` such as [`str`] it
+/// is the owned counterpart of `T`, such as a `String`.
+pub struct CowRef<'a, T: ?Sized, C: CowCopy<T> = DefaultClone> {
 	ptr: &'a CowCell<T>,
-	copy: Option<T>,
+	copy: Option<C::Owned>,
+	/// Held for as long as `copy` is `None`.
+	read: Option<ReadGuard<'a>>,
+	version: usize,
+	cloner: C,
 }
 
-impl<'a, T> CowRef<'a, T> {
+impl<'a, T: ?Sized, C: CowCopy<T>> CowRef<'a, T, C> {
 	/// A new borrow from a [`CowCell`].
 	#[inline]
-	const fn new(ptr: &'a CowCell<T>) -> Self {
-		Self { ptr, copy: None }
+	fn new(ptr: &'a CowCell<T>, cloner: C) -> Self {
+		let read = Some(ptr.state.read());
+		Self {
+			ptr,
+			copy: None,
+			read,
+			version: ptr.version(),
+			cloner,
+		}
 	}
 
 	/// Returns a reference to the [`CowCell`] that originated this
@@ -95,10 +406,12 @@ impl<'a, T> CowRef<'a, T> {
 
 	/// Get an immutable reference to the inner value.
 	#[inline]
-	pub const fn get_ref(&self) -> &T {
+	pub fn get_ref(&self) -> &T {
 		match self.copy.as_ref() {
-			Some(v) => v,
-			None => &self.ptr.val,
+			Some(v) => borrow::Borrow::borrow(v),
+			// SAFETY: without a copy, this borrow holds a read on
+			// the cell.
+			None => unsafe { self.ptr.get_unchecked() },
 		}
 	}
 
@@ -108,32 +421,117 @@ impl<'a, T> CowRef<'a, T> {
 	pub const fn is_cloned(&self) -> bool {
 		self.copy.is_some()
 	}
+
+	/// Returns the version of the originating [`CowCell`] this
+	/// borrow was made from.
+	#[inline]
+	pub const fn version(&self) -> usize {
+		self.version
+	}
+
+	/// Store the copy of the inner value, releasing the read on the
+	/// cell and recycling the previous copy, if any.
+	#[inline]
+	fn set_copy(&mut self, copy: C::Owned) {
+		let old = self.copy.replace(copy);
+		self.read = None;
+		if let Some(old) = old {
+			self.cloner.recycle(old);
+		}
+	}
 }
 
-impl<'a, T: Clone> CowRef<'a, T> {
-	/// Get a mutable reference to the inner value, cloning the
-	/// original value if necessary.
+impl<'a, T: ?Sized, C: CowClone<T>> CowRef<'a, T, C> {
+	/// Get a mutable reference to the copy of the inner value, making
+	/// it if necessary.
 	#[inline]
-	pub fn get_mut(&mut self) -> &mut T {
-		self.copy.get_or_insert_with(|| self.ptr.val.clone())
+	pub fn get_mut(&mut self) -> &mut C::Owned {
+		if self.copy.is_none() {
+			// SAFETY: without a copy, this borrow holds a read on
+			// the cell.
+			let val = unsafe { self.ptr.get_unchecked() };
+			let copy = self.cloner.cow_clone(val);
+			self.set_copy(copy);
+		}
+		self.copy.as_mut().unwrap()
 	}
 
-	/// Consume the [`CowRef`], retrieving the inner value. This
-	/// clones the original value if a copy was not already made.
+	/// Consume the [`CowRef`], retrieving the copy of the inner value.
+	/// This makes the copy if it was not already made.
 	#[inline]
-	pub fn into_inner(self) -> T {
-		self.copy.unwrap_or_else(|| self.ptr.val.clone())
+	pub fn into_inner(mut self) -> C::Owned {
+		match self.copy.take() {
+			Some(v) => v,
+			None => {
+				// SAFETY: without a copy, this borrow holds a read
+				// on the cell.
+				let val = unsafe { self.ptr.get_unchecked() };
+				self.cloner.cow_clone(val)
+			}
+		}
+	}
+}
+
+impl<'a, T, C: CowCopy<T, Owned = T>> CowRef<'a, T, C> {
+	/// Publish the copy made by this borrow as the new value of the
+	/// originating [`CowCell`].
+	///
+	/// If no copy was made there is nothing to publish, and this
+	/// returns `Ok`. Otherwise the commit is rejected, leaving the
+	/// cell untouched and handing back the copy, if:
+	///
+	/// * Other borrows are still reading the original value
+	///   ([`CommitError::Busy`]). Borrows that have made their own
+	///   copy do not read the original, so they never block a commit.
+	/// * The cell was committed to after this borrow was made
+	///   ([`CommitError::Conflict`]). When several borrows made from
+	///   the same version commit, the first one wins.
+	pub fn commit(mut self) -> Result<(), CommitError<T>> {
+		let Some(copy) = self.copy.take() else {
+			return Ok(());
+		};
+		let Some(write) = self.ptr.state.try_write() else {
+			return Err(CommitError::Busy(copy));
+		};
+		if self.ptr.version() != self.version {
+			return Err(CommitError::Conflict(copy));
+		}
+		// SAFETY: the write guard excludes every other access to
+		// the value.
+		let old = unsafe {
+			core::mem::replace(&mut *self.ptr.val.get(), copy)
+		};
+		self.ptr.version.store(
+			self.version.wrapping_add(1),
+			atomic::Ordering::Release,
+		);
+		// Recycle the old value outside of the write, in case its
+		// destructor uses the cell.
+		drop(write);
+		self.cloner.recycle(old);
+		Ok(())
 	}
 }
 
-impl<'a, T> From<&'a CowCell<T>> for CowRef<'a, T> {
+impl<'a, T: ?Sized + OwnedCopy> From<&'a CowCell<T>>
+	for CowRef<'a, T>
+{
 	#[inline]
 	fn from(cell: &'a CowCell<T>) -> Self {
-		Self::new(cell)
+		Self::new(cell, DefaultClone)
 	}
 }
 
-impl<T> Deref for CowRef<'_, T> {
+impl<T: ?Sized, C: CowCopy<T>> Drop for CowRef<'_, T, C> {
+	#[inline]
+	fn drop(&mut self) {
+		if let Some(copy) = self.copy.take() {
+			self.cloner.recycle(copy);
+		}
+	}
+}
+
+impl<T: ?Sized, C: CowCopy<T>> Deref for CowRef<'_, T, C> {
 	type Target = T;
 
 	#[inline]
@@ -142,14 +540,192 @@ impl<T> Deref for CowRef<'_, T> {
 	}
 }
 
-impl<T: Clone> DerefMut for CowRef<'_, T> {
+impl<T: ?Sized, C: CowClone<T>> DerefMut for CowRef<'_, T, C>
+where
+	C::Owned: BorrowMut<T>,
+{
 	#[inline]
 	fn deref_mut(&mut self) -> &mut Self::Target {
-		self.get_mut()
+		self.get_mut().borrow_mut()
+	}
+}
+
+impl<T, C> fmt::Debug for CowRef<'_, T, C>
+where
+	T: ?Sized + fmt::Debug,
+	C: CowCopy<T>,
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("CowRef")
+			.field("val", &self.get_ref())
+			.field("is_cloned", &self.is_cloned())
+			.field("version", &self.version)
+			.finish()
 	}
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
+
+	#[test]
+	fn commit_publishes_copy() {
+		let cell = CowCell::new(vec![1, 2]);
+		let mut borrow = cell.borrow();
+		borrow.push(3);
+		borrow.commit().unwrap();
+		assert_eq!(*cell.borrow(), [1, 2, 3]);
+	}
+
+	#[test]
+	fn commit_without_copy() {
+		let cell = CowCell::new(1);
+		let reader = cell.borrow();
+		cell.borrow().commit().unwrap();
+		assert_eq!(*reader, 1);
+	}
+
+	#[test]
+	fn commit_blocked_by_reader() {
+		let cell = CowCell::new(1);
+		let reader = cell.borrow();
+		let mut writer = cell.borrow();
+		*writer = 2;
+		assert_eq!(writer.commit(), Err(CommitError::Busy(2)));
+		assert_eq!(*reader, 1);
+		drop(reader);
+
+		let mut writer = cell.borrow();
+		*writer = 2;
+		writer.commit().unwrap();
+		assert_eq!(*cell.borrow(), 2);
+	}
+
+	#[test]
+	fn first_commit_wins() {
+		let cell = CowCell::new(0);
+		let mut a = cell.borrow();
+		let mut b = cell.borrow();
+		*a = 1;
+		*b = 2;
+		a.commit().unwrap();
+		assert_eq!(b.commit(), Err(CommitError::Conflict(2)));
+		assert_eq!(*cell.borrow(), 1);
+		assert_eq!(cell.version(), 1);
+	}
+
+	#[test]
+	fn update_with_retry() {
+		let cell = CowCell::new(0u32);
+		std::thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..100 {
+						while let Err(e) =
+							cell.update_with_retry(|v| *v += 1)
+						{
+							assert!(!e.is_conflict());
+						}
+					}
+				});
+			}
+		});
+		assert_eq!(cell.into_inner(), 400);
+	}
+
+	#[test]
+	fn speculate_panic() {
+		let cell = CowCell::new(1);
+		let res = std::panic::catch_unwind(|| {
+			cell.speculate(|v| -> Result<(), ()> {
+				**v = 2;
+				panic!();
+			})
+		});
+		assert!(res.is_err());
+		assert_eq!(cell.speculate(|v| Ok::<_, ()>(**v)), Ok(1));
+	}
+
+	#[test]
+	#[cfg(feature = "alloc")]
+	fn unsized_borrow() {
+		let cell: Box<CowCell<str>> = Box::from("abc");
+		let mut borrow = cell.borrow();
+		assert_eq!(&*borrow, "abc");
+		borrow.make_ascii_uppercase();
+		assert_eq!(borrow.into_inner(), "ABC");
+		assert_eq!(&*cell.borrow(), "abc");
+
+		let cell: Box<CowCell<[u16]>> = Box::from(&[1, 2][..]);
+		assert_eq!(cell.borrow().into_inner(), [1, 2]);
+		let cell: Box<CowCell<[u8]>> = Box::from(&[][..]);
+		assert!(cell.borrow().is_empty());
+	}
+
+	#[test]
+	#[cfg(feature = "alloc")]
+	fn commit_in_place() {
+		struct Reader;
+		impl Drop for Reader {
+			fn drop(&mut self) {
+				// This would wait forever under the write.
+				drop(CELL.borrow());
+			}
+		}
+		static CELL: CowCell<Vec<Reader>> = CowCell::new(Vec::new());
+
+		let mut read = CELL.state.read();
+		let reader = CELL.borrow();
+		assert!(!CELL.commit_in_place(&mut read, |_, _: &mut ()| {}));
+		drop(reader);
+		assert!(CELL.commit_in_place(&mut read, |v, _: &mut ()| {
+			v.push(Reader);
+		}));
+		assert!(CELL.commit_in_place(
+			&mut read,
+			|v, displaced: &mut Vec<_>| displaced.append(v),
+		));
+		drop(read);
+		assert_eq!(CELL.version(), 2);
+		assert!(CELL.borrow().is_empty());
+	}
+
+	#[test]
+	fn borrow_non_clone() {
+		let cell = CowCell::new(std::sync::Mutex::new(1));
+		let borrow = cell.borrow();
+		*borrow.lock().unwrap() += 1;
+		assert!(!borrow.is_cloned());
+		drop(borrow);
+		assert_eq!(cell.into_inner().into_inner().unwrap(), 2);
+	}
+
+	#[test]
+	fn borrow_with_non_clone() {
+		#[derive(Debug, PartialEq)]
+		struct Handle(u32);
+
+		let cell = CowCell::new(Handle(1));
+		let mut dups = 0;
+		let mut borrow = cell.borrow_with(|h: &Handle| {
+			dups += 1;
+			Handle(h.0 + 1)
+		});
+		assert_eq!(*borrow, Handle(1));
+		borrow.0 *= 10;
+		borrow.0 += 1;
+		borrow.commit().unwrap();
+		assert_eq!(dups, 1);
+		assert_eq!(cell.into_inner(), Handle(21));
+	}
+
+	#[test]
+	fn into_inner_releases_read() {
+		let cell = CowCell::new(1);
+		assert_eq!(cell.borrow().into_inner(), 1);
+		let mut writer = cell.borrow();
+		*writer = 2;
+		writer.commit().unwrap();
+		assert_eq!(cell.into_inner(), 2);
+	}
 }
@@ -0,0 +1,500 @@
+//! Overlay borrows of maps that only store the modified entries.
+
+use crate::state::ReadGuard;
+use crate::{CommitError, CowCell};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+
+/// A map that can be the base of a [`CowMap`].
+///
+/// This is implemented for [`BTreeMap`] and, with the `std` feature,
+/// for `HashMap`. Entries are looked up through [`OverlayLookup`].
+pub trait OverlayMap {
+	/// The type of the keys.
+	type Key;
+	/// The type of the values.
+	type Value;
+	/// The map holding the changes made to a base map, where removed
+	/// keys map to [`None`].
+	type Delta: OverlayMap<Key = Self::Key, Value = Option<Self::Value>>
+		+ OverlayLookup<Self::Key>
+		+ Default;
+
+	/// Set the value of `key`, returning the previous one.
+	fn insert(
+		&mut self,
+		key: Self::Key,
+		val: Self::Value,
+	) -> Option<Self::Value>;
+
+	/// Returns the number of entries.
+	fn len(&self) -> usize;
+
+	/// Returns [`true`] if there are no entries.
+	#[inline]
+	fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Returns an iterator over the entries.
+	fn iter(
+		&self,
+	) -> impl Iterator<Item = (&Self::Key, &Self::Value)>;
+
+	/// Returns an iterator over the owned entries.
+	fn into_entries(
+		self,
+	) -> impl Iterator<Item = (Self::Key, Self::Value)>;
+}
+
+/// Looking up the entries of an [`OverlayMap`] by any borrowed form
+/// `Q` of its keys, such as `str` for `String` keys, as the methods of
+/// [`BTreeMap`] and `HashMap` do.
+pub trait OverlayLookup<Q: ?Sized>: OverlayMap {
+	/// Returns a reference to the value of `key`.
+	fn get(&self, key: &Q) -> Option<&Self::Value>;
+
+	/// Returns the stored key of `key` and a reference to its value.
+	fn get_key_value(
+		&self,
+		key: &Q,
+	) -> Option<(&Self::Key, &Self::Value)>;
+
+	/// Returns a mutable reference to the value of `key`.
+	fn get_mut(&mut self, key: &Q) -> Option<&mut Self::Value>;
+
+	/// Remove `key`, returning its value.
+	fn remove(&mut self, key: &Q) -> Option<Self::Value>;
+
+	/// Remove `key`, returning the stored key and its value.
+	fn remove_entry(
+		&mut self,
+		key: &Q,
+	) -> Option<(Self::Key, Self::Value)>;
+}
+
+macro_rules! impl_overlay_map {
+	($map:ident<K, V $(, $s:ident)?>, $($bound:tt)*) => {
+		impl<K: $($bound)*, V $(, $s: core::hash::BuildHasher + Default)?>
+			OverlayMap for $map<K, V $(, $s)?>
+		{
+			type Key = K;
+			type Value = V;
+			type Delta = $map<K, Option<V> $(, $s)?>;
+
+			#[inline]
+			fn insert(&mut self, key: K, val: V) -> Option<V> {
+				$map::insert(self, key, val)
+			}
+
+			#[inline]
+			fn len(&self) -> usize {
+				$map::len(self)
+			}
+
+			#[inline]
+			fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+				$map::iter(self)
+			}
+
+			#[inline]
+			fn into_entries(self) -> impl Iterator<Item = (K, V)> {
+				self.into_iter()
+			}
+		}
+
+		impl<K, V, Q $(, $s)?> OverlayLookup<Q> for $map<K, V $(, $s)?>
+		where
+			K: Borrow<Q> + $($bound)*,
+			Q: ?Sized + $($bound)*,
+			$($s: core::hash::BuildHasher + Default,)?
+		{
+			#[inline]
+			fn get(&self, key: &Q) -> Option<&V> {
+				$map::get(self, key)
+			}
+
+			#[inline]
+			fn get_key_value(&self, key: &Q) -> Option<(&K, &V)> {
+				$map::get_key_value(self, key)
+			}
+
+			#[inline]
+			fn get_mut(&mut self, key: &Q) -> Option<&mut V> {
+				$map::get_mut(self, key)
+			}
+
+			#[inline]
+			fn remove(&mut self, key: &Q) -> Option<V> {
+				$map::remove(self, key)
+			}
+
+			#[inline]
+			fn remove_entry(&mut self, key: &Q) -> Option<(K, V)> {
+				$map::remove_entry(self, key)
+			}
+		}
+	};
+}
+
+impl_overlay_map!(BTreeMap<K, V>, Ord);
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+impl_overlay_map!(HashMap<K, V, S>, Eq + core::hash::Hash);
+
+/// A borrow of a map that records the changes made to it in a delta,
+/// instead of cloning the whole map.
+///
+/// Lookups consult the delta first and then the original map, where
+/// removed keys are marked with tombstones. The borrow reads the
+/// original value of the cell for as long as it is alive, so like a
+/// [`CowChunks`](crate::CowChunks) it blocks commits from other
+/// borrows. Its changes are published with [`CowMap::commit`], which
+/// applies them to the cell in place.
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use cowcell::CowCell;
+/// use std::collections::HashMap;
+///
+/// let cell = CowCell::new(HashMap::from([(1, "a"), (2, "b")]));
+/// let mut map = cell.overlay();
+/// map.insert(3, "c");
+/// map.remove(&1);
+/// *map.get_mut(&2).unwrap() = "B";
+///
+/// assert_eq!(map.len(), 2);
+/// assert_eq!(map.get(&1), None);
+/// assert_eq!(map.materialize(), HashMap::from([(2, "B"), (3, "c")]));
+///
+/// // The original map has not been modified.
+/// assert_eq!(map.get_cell().borrow().len(), 2);
+/// # }
+/// ```
+pub struct CowMap<'a, M: OverlayMap> {
+	ptr: &'a CowCell<M>,
+	read: ReadGuard<'a>,
+	delta: M::Delta,
+	len: usize,
+}
+
+impl<M: OverlayMap> CowCell<M> {
+	/// Create a new borrow that records the changes made to the map
+	/// in a delta.
+	#[inline]
+	pub fn overlay(&self) -> CowMap<'_, M> {
+		CowMap::new(self)
+	}
+}
+
+impl<'a, M: OverlayMap> CowMap<'a, M> {
+	/// A new borrow from a [`CowCell`].
+	pub fn new(ptr: &'a CowCell<M>) -> Self {
+		let read = ptr.state.read();
+		// SAFETY: we hold a read on the cell.
+		let len = unsafe { ptr.get_unchecked() }.len();
+		Self {
+			ptr,
+			read,
+			delta: M::Delta::default(),
+			len,
+		}
+	}
+
+	/// Returns a reference to the [`CowCell`] that originated this
+	/// borrow.
+	#[inline]
+	pub const fn get_cell(&self) -> &CowCell<M> {
+		self.ptr
+	}
+
+	/// The original map.
+	#[inline]
+	fn base(&self) -> &M {
+		// SAFETY: this borrow holds a read on the cell.
+		unsafe { self.ptr.get_unchecked() }
+	}
+
+	/// Returns the number of entries in the map.
+	#[inline]
+	pub const fn len(&self) -> usize {
+		self.len
+	}
+
+	/// Returns [`true`] if the map has no entries.
+	#[inline]
+	pub const fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Returns [`true`] if changes have been made to the map.
+	#[inline]
+	pub fn is_modified(&self) -> bool {
+		!self.delta.is_empty()
+	}
+
+	/// Returns an iterator over the changes made to the map, where
+	/// removed keys map to [`None`].
+	#[inline]
+	pub fn changes(
+		&self,
+	) -> impl Iterator<Item = (&M::Key, Option<&M::Value>)> {
+		self.delta.iter().map(|(k, v)| (k, v.as_ref()))
+	}
+
+	/// Returns a reference to the value of `key`.
+	#[inline]
+	pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&M::Value>
+	where
+		M: OverlayLookup<Q>,
+		M::Delta: OverlayLookup<Q>,
+	{
+		match self.delta.get(key) {
+			Some(val) => val.as_ref(),
+			None => self.base().get(key),
+		}
+	}
+
+	/// Returns [`true`] if the map has an entry for `key`.
+	#[inline]
+	pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool
+	where
+		M: OverlayLookup<Q>,
+		M::Delta: OverlayLookup<Q>,
+	{
+		self.get(key).is_some()
+	}
+}
+
+impl<'a, M: OverlayLookup<<M as OverlayMap>::Key>> CowMap<'a, M> {
+	/// Set the value of `key`. Returns [`true`] if the map did not
+	/// have an entry for it.
+	pub fn insert(&mut self, key: M::Key, val: M::Value) -> bool {
+		let new = !self.contains_key(&key);
+		self.len += usize::from(new);
+		self.delta.insert(key, Some(val));
+		new
+	}
+
+	/// Returns an iterator over the entries of the map, starting with
+	/// those of the original map, followed by the inserted ones.
+	pub fn iter(&self) -> impl Iterator<Item = (&M::Key, &M::Value)> {
+		let base = self.base();
+		let kept = base.iter().filter_map(|(k, v)| {
+			match self.delta.get(k) {
+				Some(val) => val.as_ref().map(|val| (k, val)),
+				None => Some((k, v)),
+			}
+		});
+		let inserted = self.delta.iter().filter_map(|(k, v)| {
+			match base.get(k) {
+				Some(_) => None,
+				None => v.as_ref().map(|v| (k, v)),
+			}
+		});
+		kept.chain(inserted)
+	}
+
+	/// Write the changes into the originating [`CowCell`], handing
+	/// back the borrow in [`CommitError::Busy`] if other borrows are
+	/// reading the cell.
+	pub fn commit(mut self) -> Result<(), CommitError<Self>> {
+		if !self.is_modified() {
+			return Ok(());
+		}
+		let delta = &mut self.delta;
+		let done = self.ptr.commit_in_place(
+			&mut self.read,
+			|map, displaced: &mut Vec<_>| {
+				let delta = core::mem::take(delta);
+				apply(map, delta, |e| displaced.push(e));
+			},
+		);
+		if !done {
+			return Err(CommitError::Busy(self));
+		}
+		Ok(())
+	}
+}
+
+impl<'a, M: OverlayMap> CowMap<'a, M>
+where
+	M::Key: Clone,
+{
+	/// Remove `key` from the map, leaving a tombstone if it is in the
+	/// original map. Returns [`true`] if the map had an entry for it.
+	pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> bool
+	where
+		M: OverlayLookup<Q>,
+		M::Delta: OverlayLookup<Q>,
+	{
+		let old = self.contains_key(key);
+		self.len -= usize::from(old);
+		match self.base().get_key_value(key) {
+			Some((k, _)) => {
+				let k = k.clone();
+				self.delta.insert(k, None);
+			}
+			None => {
+				self.delta.remove(key);
+			}
+		}
+		old
+	}
+}
+
+impl<'a, M: OverlayMap> CowMap<'a, M>
+where
+	M::Key: Clone,
+	M::Value: Clone,
+{
+	/// Returns a mutable reference to the value of `key`, copying it
+	/// from the original map if necessary.
+	pub fn get_mut<Q: ?Sized>(
+		&mut self,
+		key: &Q,
+	) -> Option<&mut M::Value>
+	where
+		M: OverlayLookup<Q>,
+		M::Delta: OverlayLookup<Q>,
+	{
+		if self.delta.get(key).is_none() {
+			let (k, v) = self.base().get_key_value(key)?;
+			let (k, v) = (k.clone(), v.clone());
+			self.delta.insert(k, Some(v));
+		}
+		self.delta.get_mut(key)?.as_mut()
+	}
+
+	/// Build the map with the changes applied, leaving the borrow
+	/// untouched.
+	pub fn materialize(&self) -> M
+	where
+		M: OverlayLookup<<M as OverlayMap>::Key> + Clone,
+	{
+		let mut map = self.base().clone();
+		for (k, v) in self.delta.iter() {
+			match v {
+				Some(v) => map.insert(k.clone(), v.clone()),
+				None => map.remove(k),
+			};
+		}
+		map
+	}
+}
+
+impl<'a, M> CowMap<'a, M>
+where
+	M: OverlayLookup<<M as OverlayMap>::Key> + Clone,
+{
+	/// Consume the [`CowMap`], retrieving the map with the changes
+	/// applied.
+	pub fn into_inner(self) -> M {
+		let mut map = self.base().clone();
+		apply(&mut map, self.delta, drop);
+		map
+	}
+}
+
+/// Apply `delta` to `map`, handing the keys and values it replaces to
+/// `displaced` instead of dropping them.
+fn apply<M: OverlayLookup<<M as OverlayMap>::Key>>(
+	map: &mut M,
+	delta: M::Delta,
+	mut displaced: impl FnMut((M::Key, Option<M::Value>)),
+) {
+	for (k, v) in delta.into_entries() {
+		match v {
+			Some(v) => match map.get_mut(&k) {
+				Some(old) => {
+					let old = core::mem::replace(old, v);
+					displaced((k, Some(old)));
+				}
+				None => {
+					map.insert(k, v);
+				}
+			},
+			None => {
+				if let Some((old, v)) = map.remove_entry(&k) {
+					displaced((old, Some(v)));
+				}
+				displaced((k, None));
+			}
+		}
+	}
+}
+
+impl<M> fmt::Debug for CowMap<'_, M>
+where
+	M: OverlayLookup<<M as OverlayMap>::Key>,
+	M::Key: fmt::Debug,
+	M::Value: fmt::Debug,
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_map().entries(self.iter()).finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::vec::Vec;
+
+	#[test]
+	fn tombstones() {
+		let cell = CowCell::new(BTreeMap::from([(1, 1), (2, 2)]));
+		let mut map = cell.overlay();
+		assert!(map.remove(&1));
+		assert!(!map.remove(&1));
+		assert!(map.insert(1, 10));
+		assert!(map.insert(3, 3));
+		assert!(map.remove(&3));
+		assert_eq!(map.len(), 2);
+		assert_eq!(
+			map.iter().collect::<Vec<_>>(),
+			[(&1, &10), (&2, &2)]
+		);
+		assert_eq!(
+			map.changes().collect::<Vec<_>>(),
+			[(&1, Some(&10))]
+		);
+		assert_eq!(
+			map.into_inner(),
+			BTreeMap::from([(1, 10), (2, 2)])
+		);
+	}
+
+	#[test]
+	fn commit() {
+		let cell = CowCell::new(BTreeMap::from([(1, 1), (2, 2)]));
+		let mut map = cell.overlay();
+		map.remove(&1);
+		*map.get_mut(&2).unwrap() = 3;
+		map.insert(4, 4);
+		map.commit().unwrap();
+		assert_eq!(*cell.borrow(), BTreeMap::from([(2, 3), (4, 4)]));
+		assert_eq!(cell.version(), 1);
+	}
+
+	#[test]
+	fn borrowed_keys() {
+		use alloc::string::String;
+
+		let cell = CowCell::new(BTreeMap::from([
+			(String::from("a"), 1),
+			(String::from("b"), 2),
+		]));
+		let mut map = cell.overlay();
+		*map.get_mut("a").unwrap() += 10;
+		assert!(map.remove("b"));
+		assert!(!map.contains_key("b"));
+		assert_eq!(map.get("a"), Some(&11));
+		assert_eq!(
+			map.into_inner(),
+			BTreeMap::from([(String::from("a"), 11)])
+		);
+	}
+}
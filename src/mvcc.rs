@@ -0,0 +1,326 @@
+//! A multi-version cell with pinned reader snapshots.
+
+use crate::state::BorrowState;
+use crate::CommitError;
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+/// A thread-safe cell keeping every version of its value that is
+/// still pinned by a reader.
+///
+/// Writers commit new versions, each with its own number. Readers
+/// pin a version with [`MvccCell::pin`] or [`MvccCell::pin_at`], and
+/// get an [`MvccRef`] that reads it consistently for as long as it is
+/// alive, however many versions are committed in the meantime. A
+/// version is reclaimed as soon as it is neither the latest one nor
+/// pinned by a reader.
+///
+/// ```rust
+/// use cowcell::MvccCell;
+///
+/// let cell = MvccCell::new(vec![1]);
+/// let report = cell.pin();
+///
+/// for i in 2..5 {
+///     let mut ingest = cell.pin();
+///     ingest.push(i);
+///     ingest.commit().unwrap();
+/// }
+///
+/// // The long-running reader still sees the version it pinned.
+/// assert_eq!((report.version(), &**report), (0, &[1][..]));
+/// assert_eq!(cell.pinned_versions(), [0, 3]);
+/// assert_eq!(*cell.pin_at(0).unwrap(), [1]);
+///
+/// drop(report);
+/// assert!(cell.pin_at(0).is_none());
+/// assert_eq!(*cell.pin(), [1, 2, 3, 4]);
+/// ```
+pub struct MvccCell<T> {
+	state: BorrowState,
+	versions: UnsafeCell<Versions<T>>,
+}
+
+struct Versions<T> {
+	latest: Arc<T>,
+	version: usize,
+	/// Older versions, oldest first, which live for as long as
+	/// readers pin them.
+	old: VecDeque<(usize, Weak<T>)>,
+}
+
+// SAFETY: the versions are only modified under a write, which
+// `BorrowState` makes exclusive across threads. Values of `T` are
+// shared between threads and dropped by whichever reader unpins
+// them last.
+unsafe impl<T: Send + Sync> Sync for MvccCell<T> {}
+
+impl<T> MvccCell<T> {
+	/// Create a new [`MvccCell`] whose version 0 is the given value.
+	#[inline]
+	pub fn new(val: T) -> Self {
+		Self {
+			state: BorrowState::new(),
+			versions: UnsafeCell::new(Versions {
+				latest: Arc::new(val),
+				version: 0,
+				old: VecDeque::new(),
+			}),
+		}
+	}
+
+	/// Run `f` on the versions while holding a read on the cell.
+	#[inline]
+	fn with_versions<R>(
+		&self,
+		f: impl FnOnce(&Versions<T>) -> R,
+	) -> R {
+		let _read = self.state.read();
+		// SAFETY: the read excludes writers.
+		f(unsafe { &*self.versions.get() })
+	}
+
+	/// Returns the number of the latest version.
+	#[inline]
+	pub fn version(&self) -> usize {
+		self.with_versions(|v| v.version)
+	}
+
+	/// Pin the latest version.
+	pub fn pin(&self) -> MvccRef<'_, T> {
+		let (snap, version) = self
+			.with_versions(|v| (Arc::clone(&v.latest), v.version));
+		MvccRef {
+			cell: self,
+			snap,
+			version,
+			copy: None,
+		}
+	}
+
+	/// Pin the given version, if it is the latest one or is still
+	/// pinned by another reader.
+	pub fn pin_at(&self, version: usize) -> Option<MvccRef<'_, T>> {
+		let snap = self.with_versions(|v| {
+			if version == v.version {
+				return Some(Arc::clone(&v.latest));
+			}
+			let i =
+				v.old.binary_search_by_key(&version, |e| e.0).ok()?;
+			v.old[i].1.upgrade()
+		})?;
+		Some(MvccRef {
+			cell: self,
+			snap,
+			version,
+			copy: None,
+		})
+	}
+
+	/// Returns the numbers of the versions that have not been
+	/// reclaimed, in ascending order. The last one is the latest.
+	pub fn pinned_versions(&self) -> Vec<usize> {
+		self.with_versions(|v| {
+			v.old
+				.iter()
+				.filter(|(_, w)| w.strong_count() > 0)
+				.map(|(n, _)| *n)
+				.chain([v.version])
+				.collect()
+		})
+	}
+
+	/// Commit `val` as a new version, if `base` is still the latest
+	/// one. Returns the number of the new version.
+	fn publish(
+		&self,
+		base: Option<usize>,
+		val: T,
+	) -> Result<usize, CommitError<T>> {
+		let new = Arc::new(val);
+		let write = self.state.write();
+		// SAFETY: the write guard excludes every other access.
+		let v = unsafe { &mut *self.versions.get() };
+		if base.is_some_and(|base| base != v.version) {
+			drop(write);
+			let val = Arc::into_inner(new).unwrap();
+			return Err(CommitError::Conflict(val));
+		}
+		let old = core::mem::replace(&mut v.latest, new);
+		v.old.retain(|(_, w)| w.strong_count() > 0);
+		v.old.push_back((v.version, Arc::downgrade(&old)));
+		v.version = v.version.wrapping_add(1);
+		let version = v.version;
+		// Drop the old version outside of the write, in case its
+		// destructor uses the cell.
+		drop(write);
+		drop(old);
+		Ok(version)
+	}
+
+	/// Commit a new version, regardless of the latest one. Returns
+	/// the number of the new version.
+	#[inline]
+	pub fn store(&self, val: T) -> usize {
+		match self.publish(None, val) {
+			Ok(version) => version,
+			Err(_) => unreachable!(),
+		}
+	}
+}
+
+impl<T: Default> Default for MvccCell<T> {
+	#[inline]
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T: fmt::Debug> fmt::Debug for MvccCell<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let latest = self.pin();
+		f.debug_struct("MvccCell")
+			.field("version", &latest.version)
+			.field("val", &latest.get_ref())
+			.finish()
+	}
+}
+
+impl<T> From<T> for MvccCell<T> {
+	#[inline]
+	fn from(val: T) -> Self {
+		Self::new(val)
+	}
+}
+
+/// A reader pinning a version of an [`MvccCell`], with
+/// clone-on-write semantics on mutable access.
+///
+/// The pinned version is not reclaimed while this guard is alive.
+/// When accessed mutably, the guard clones it into a private copy,
+/// which can be committed as a new version with [`MvccRef::commit`].
+#[derive(Debug)]
+pub struct MvccRef<'a, T> {
+	cell: &'a MvccCell<T>,
+	snap: Arc<T>,
+	version: usize,
+	copy: Option<T>,
+}
+
+impl<'a, T> MvccRef<'a, T> {
+	/// Returns a reference to the [`MvccCell`] that originated this
+	/// guard.
+	#[inline]
+	pub const fn get_cell(&self) -> &'a MvccCell<T> {
+		self.cell
+	}
+
+	/// Returns the number of the pinned version.
+	#[inline]
+	pub const fn version(&self) -> usize {
+		self.version
+	}
+
+	/// Get an immutable reference to the inner value.
+	#[inline]
+	pub fn get_ref(&self) -> &T {
+		match self.copy.as_ref() {
+			Some(v) => v,
+			None => &self.snap,
+		}
+	}
+
+	/// Returns [`true`] if this [`MvccRef`] has made a copy of the
+	/// pinned version.
+	#[inline]
+	pub const fn is_cloned(&self) -> bool {
+		self.copy.is_some()
+	}
+
+	/// Commit the copy made by this guard as a new version of the
+	/// originating [`MvccCell`], returning its number.
+	///
+	/// If no copy was made there is nothing to commit, and this
+	/// returns the pinned version. If the pinned version is not the
+	/// latest one, the cell is left untouched and the copy is handed
+	/// back in [`CommitError::Conflict`].
+	pub fn commit(self) -> Result<usize, CommitError<T>> {
+		match self.copy {
+			Some(copy) => self.cell.publish(Some(self.version), copy),
+			None => Ok(self.version),
+		}
+	}
+}
+
+impl<'a, T: Clone> MvccRef<'a, T> {
+	/// Get a mutable reference to the inner value, cloning the
+	/// pinned version if necessary.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		let snap = &self.snap;
+		self.copy.get_or_insert_with(|| T::clone(snap))
+	}
+
+	/// Consume the [`MvccRef`], retrieving the inner value. This
+	/// clones the pinned version if a copy was not already made and
+	/// other references to it exist.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		match self.copy {
+			Some(v) => v,
+			None => Arc::unwrap_or_clone(self.snap),
+		}
+	}
+}
+
+impl<T> Deref for MvccRef<'_, T> {
+	type Target = T;
+
+	#[inline]
+	fn deref(&self) -> &Self::Target {
+		self.get_ref()
+	}
+}
+
+impl<T: Clone> DerefMut for MvccRef<'_, T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.get_mut()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::rc::Rc;
+
+	#[test]
+	fn commit_conflict() {
+		let cell = MvccCell::new(0);
+		let mut a = cell.pin();
+		let mut b = cell.pin();
+		*a = 1;
+		*b = 2;
+		assert_eq!(a.commit(), Ok(1));
+		assert_eq!(b.commit(), Err(CommitError::Conflict(2)));
+		assert_eq!(cell.store(3), 2);
+		assert_eq!(*cell.pin(), 3);
+	}
+
+	#[test]
+	fn reclaims_unpinned() {
+		let val = Rc::new(());
+		let cell = MvccCell::new(Rc::clone(&val));
+		let pinned = cell.pin();
+		cell.store(Rc::new(()));
+		assert_eq!(Rc::strong_count(&val), 2);
+		drop(pinned);
+		assert_eq!(Rc::strong_count(&val), 1);
+		cell.store(Rc::new(()));
+		assert_eq!(cell.pinned_versions(), [2]);
+	}
+}
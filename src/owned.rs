@@ -0,0 +1,164 @@
+//! Lifetime-free clone-on-write borrows backed by [`Arc`].
+
+use crate::SyncCowCell;
+use alloc::sync::Arc;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+/// An owned borrow with clone-on-write semantics on mutable access.
+///
+/// Unlike a [`CowRef`](crate::CowRef), this type holds an [`Arc`] to
+/// the original value instead of a reference to a cell, so it has no
+/// lifetime and can be stored, returned or moved to spawned tasks.
+/// The original value is only cloned when it is shared with other
+/// owners; when this borrow holds the last strong reference, it is
+/// unwrapped instead.
+///
+/// ```rust
+/// use cowcell::CowOwned;
+/// use std::sync::Arc;
+///
+/// let base = Arc::new(vec![1, 2]);
+/// let mut borrow = CowOwned::from_arc(Arc::clone(&base));
+///
+/// let handle = std::thread::spawn(move || {
+///     borrow.push(3);
+///     borrow
+/// });
+/// let borrow = handle.join().unwrap();
+/// assert!(borrow.is_cloned());
+/// assert_eq!(*borrow, [1, 2, 3]);
+/// assert_eq!(*base, [1, 2]);
+/// ```
+pub struct CowOwned<T> {
+	ptr: Arc<T>,
+	cloned: bool,
+}
+
+impl<T> CowOwned<T> {
+	/// A new borrow owning the given value.
+	#[inline]
+	pub fn new(val: T) -> Self {
+		Self::from_arc(Arc::new(val))
+	}
+
+	/// A new borrow over the value behind the given [`Arc`].
+	#[inline]
+	pub const fn from_arc(ptr: Arc<T>) -> Self {
+		Self { ptr, cloned: false }
+	}
+
+	/// Get an immutable reference to the inner value.
+	#[inline]
+	pub fn get_ref(&self) -> &T {
+		&self.ptr
+	}
+
+	/// Returns [`true`] if this [`CowOwned`] has made a copy of the
+	/// original value, or taken it over as its last owner.
+	#[inline]
+	pub const fn is_cloned(&self) -> bool {
+		self.cloned
+	}
+
+	/// Consume the [`CowOwned`], retrieving the [`Arc`] holding the
+	/// inner value.
+	#[inline]
+	pub fn into_arc(self) -> Arc<T> {
+		self.ptr
+	}
+}
+
+impl<T: Clone> CowOwned<T> {
+	/// Get a mutable reference to the inner value, cloning the
+	/// original value if it is shared with other owners.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		self.cloned = true;
+		Arc::make_mut(&mut self.ptr)
+	}
+
+	/// Consume the [`CowOwned`], retrieving the inner value. This
+	/// clones the original value if it is shared with other owners.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		Arc::unwrap_or_clone(self.ptr)
+	}
+}
+
+impl<T> SyncCowCell<T> {
+	/// Create a new owned borrow with copy-on-write semantics over
+	/// the current snapshot.
+	#[inline]
+	pub fn borrow_owned(&self) -> CowOwned<T> {
+		CowOwned::from_arc(self.load())
+	}
+}
+
+impl<T> Clone for CowOwned<T> {
+	#[inline]
+	fn clone(&self) -> Self {
+		Self {
+			ptr: Arc::clone(&self.ptr),
+			cloned: self.cloned,
+		}
+	}
+}
+
+impl<T> From<Arc<T>> for CowOwned<T> {
+	#[inline]
+	fn from(ptr: Arc<T>) -> Self {
+		Self::from_arc(ptr)
+	}
+}
+
+impl<T> Deref for CowOwned<T> {
+	type Target = T;
+
+	#[inline]
+	fn deref(&self) -> &Self::Target {
+		self.get_ref()
+	}
+}
+
+impl<T: Clone> DerefMut for CowOwned<T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.get_mut()
+	}
+}
+
+impl<T: fmt::Debug> fmt::Debug for CowOwned<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("CowOwned")
+			.field("val", self.get_ref())
+			.field("is_cloned", &self.cloned)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn unwraps_last_owner() {
+		let base = Arc::new(vec![1]);
+		let mut borrow = CowOwned::from_arc(Arc::clone(&base));
+		let ptr = Arc::as_ptr(&base);
+		drop(base);
+		borrow.push(2);
+		assert!(borrow.is_cloned());
+		assert_eq!(Arc::as_ptr(&borrow.clone().into_arc()), ptr);
+		assert_eq!(borrow.into_inner(), [1, 2]);
+	}
+
+	#[test]
+	fn borrow_owned() {
+		let cell = SyncCowCell::new(1);
+		let mut borrow = cell.borrow_owned();
+		*borrow += 1;
+		assert_eq!(*cell.borrow(), 1);
+		assert_eq!(borrow.into_inner(), 2);
+	}
+}
@@ -0,0 +1,236 @@
+//! Page-granular clone-on-write borrows of byte buffers.
+
+use crate::{CommitError, CowCell, CowChunks};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The default size of a page, in bytes.
+pub const PAGE_SIZE: usize = 4096;
+
+/// A borrow of a byte buffer that copies it one page at a time.
+///
+/// This is a [`CowChunks`] of bytes, whose chunks are pages. Only the
+/// pages that are written to are cloned. The borrow reads the
+/// original value of the cell for as long as it is alive, so unlike a
+/// [`CowRef`](crate::CowRef) it blocks commits from other borrows
+/// even after making copies. Its own dirty pages are published with
+/// [`CowPages::commit`], which writes them into the cell in place.
+///
+/// ```rust
+/// use cowcell::CowCell;
+///
+/// let cell: Box<CowCell<[u8]>> = Box::from(&[0u8; 3 * 4096][..]);
+/// let mut pages = cell.pages();
+/// pages.write(4095, &[1, 2]);
+///
+/// // Only the two pages that were written to have been copied.
+/// assert_eq!(pages.dirty_pages().collect::<Vec<_>>(), [0, 1]);
+/// let mut buf = [0; 3];
+/// pages.read(4094, &mut buf);
+/// assert_eq!(buf, [0, 1, 2]);
+///
+/// pages.commit().unwrap();
+/// assert_eq!(cell.borrow()[4095..4097], [1, 2]);
+/// ```
+pub struct CowPages<'a>(CowChunks<'a, u8>);
+
+impl CowCell<[u8]> {
+	/// Create a new borrow that copies the buffer in pages of
+	/// [`PAGE_SIZE`] bytes.
+	#[inline]
+	pub fn pages(&self) -> CowPages<'_> {
+		CowPages::with_page_size(self, PAGE_SIZE)
+	}
+}
+
+impl<'a> CowPages<'a> {
+	/// A new borrow that copies the buffer of `ptr` in pages of
+	/// `page_size` bytes.
+	///
+	/// # Panics
+	///
+	/// If `page_size` is zero.
+	#[inline]
+	pub fn with_page_size(
+		ptr: &'a CowCell<[u8]>,
+		page_size: usize,
+	) -> Self {
+		Self(CowChunks::new(ptr, page_size))
+	}
+
+	/// Returns a reference to the [`CowCell`] that originated this
+	/// borrow.
+	#[inline]
+	pub const fn get_cell(&self) -> &CowCell<[u8]> {
+		self.0.get_cell()
+	}
+
+	/// Returns the length of the buffer, in bytes.
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+
+	/// Returns [`true`] if the buffer is empty.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	/// Returns the size of a page, in bytes. The last page may be
+	/// shorter.
+	#[inline]
+	pub const fn page_size(&self) -> usize {
+		self.0.chunk_len()
+	}
+
+	/// Returns the number of pages in the buffer.
+	#[inline]
+	pub fn page_count(&self) -> usize {
+		self.0.chunk_count()
+	}
+
+	/// Returns [`true`] if page `i` has been copied.
+	#[inline]
+	pub fn is_dirty(&self, i: usize) -> bool {
+		self.0.is_dirty(i)
+	}
+
+	/// Returns the indices of the pages that have been copied, in
+	/// ascending order.
+	#[inline]
+	pub fn dirty_pages(&self) -> impl Iterator<Item = usize> + '_ {
+		self.0.dirty_chunks().map(|(i, _)| i)
+	}
+
+	/// Get an immutable reference to page `i`.
+	///
+	/// # Panics
+	///
+	/// If `i` is out of bounds.
+	#[inline]
+	pub fn page(&self, i: usize) -> &[u8] {
+		self.0.chunk(i)
+	}
+
+	/// Get a mutable reference to page `i`, copying it if necessary.
+	///
+	/// # Panics
+	///
+	/// If `i` is out of bounds.
+	#[inline]
+	pub fn page_mut(&mut self, i: usize) -> &mut [u8] {
+		self.0.chunk_mut(i)
+	}
+
+	/// Returns an iterator over the pages of the buffer.
+	#[inline]
+	pub fn iter(&self) -> impl Iterator<Item = &[u8]> + '_ {
+		self.0.iter()
+	}
+
+	/// Copy the bytes starting at `offset` into `buf`.
+	///
+	/// # Panics
+	///
+	/// If the range to read is out of bounds.
+	#[inline]
+	pub fn read(&self, offset: usize, buf: &mut [u8]) {
+		self.0.read(offset, buf);
+	}
+
+	/// Copy `data` into the buffer starting at `offset`, copying the
+	/// pages it spans.
+	///
+	/// # Panics
+	///
+	/// If the range to write is out of bounds.
+	#[inline]
+	pub fn write(&mut self, offset: usize, data: &[u8]) {
+		self.0.write(offset, data);
+	}
+
+	/// Consume the [`CowPages`], flattening the pages into an owned
+	/// buffer.
+	#[inline]
+	pub fn into_inner(self) -> Vec<u8> {
+		self.0.into_inner()
+	}
+
+	/// Write the dirty pages into the originating [`CowCell`],
+	/// handing back the borrow in [`CommitError::Busy`] if other
+	/// borrows are reading the cell.
+	///
+	/// As this borrow reads the original value for as long as it is
+	/// alive, the cell cannot have changed since it was made, so this
+	/// never fails with [`CommitError::Conflict`].
+	#[inline]
+	pub fn commit(self) -> Result<(), CommitError<Self>> {
+		self.0
+			.commit()
+			.map_err(|e| CommitError::Busy(Self(e.into_inner())))
+	}
+}
+
+impl fmt::Debug for CowPages<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("CowPages")
+			.field("len", &self.len())
+			.field("page_size", &self.page_size())
+			.field("dirty", &self.dirty_pages().count())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloc::boxed::Box;
+
+	fn cell(len: usize) -> Box<CowCell<[u8]>> {
+		let buf = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+		Box::from(&buf[..])
+	}
+
+	#[test]
+	fn short_last_page() {
+		let cell = cell(10);
+		let mut pages = CowPages::with_page_size(&cell, 4);
+		assert_eq!(pages.page_count(), 3);
+		assert_eq!(pages.page(2), [8, 9]);
+		pages.write(7, &[0; 3]);
+		assert!(!pages.is_dirty(0));
+		assert!(pages.is_dirty(1) && pages.is_dirty(2));
+		assert_eq!(
+			pages.iter().collect::<Vec<_>>(),
+			[&[0, 1, 2, 3][..], &[4, 5, 6, 0], &[0, 0]]
+		);
+		assert_eq!(
+			pages.into_inner(),
+			[0, 1, 2, 3, 4, 5, 6, 0, 0, 0]
+		);
+		assert_eq!(cell.borrow()[7..], [7, 8, 9]);
+	}
+
+	#[test]
+	fn commit_blocked_by_reader() {
+		let cell = cell(8);
+		let reader = cell.borrow();
+		let mut pages = CowPages::with_page_size(&cell, 4);
+		pages.write(0, &[9]);
+		let err = pages.commit().unwrap_err();
+		assert!(!err.is_conflict());
+		let pages = err.into_inner();
+		drop(reader);
+		pages.commit().unwrap();
+		assert_eq!(cell.borrow()[..2], [9, 1]);
+		assert_eq!(cell.version(), 1);
+	}
+
+	#[test]
+	#[should_panic = "write out of bounds"]
+	fn write_out_of_bounds() {
+		let cell = cell(8);
+		cell.pages().write(7, &[0, 0]);
+	}
+}
@@ -0,0 +1,261 @@
+//! Path-copying borrows of trees of shared nodes.
+
+use crate::{ChildRef, CowClone, CowLayer, CowRef};
+use alloc::sync::Arc;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+/// A borrow of a node shared through an [`Arc`] inside the value of
+/// another borrow, created with [`CowRef::descend`].
+///
+/// Descending from node to node down a tree and writing to the last
+/// one only clones the nodes on the path from the root: committing a
+/// [`PathRef`] replaces the [`Arc`] of its node in a copy of its
+/// parent, so every untouched subtree stays shared with the original
+/// tree, as in persistent data structures.
+///
+/// Subtrees must be linked through [`Arc`]s rather than nested
+/// `CowCell`s, as cloning a cell clones its whole value.
+///
+/// ```rust
+/// use cowcell::CowCell;
+/// use std::sync::Arc;
+///
+/// #[derive(Clone)]
+/// struct Node {
+///     val: u32,
+///     children: Vec<Arc<Node>>,
+/// }
+///
+/// let leaf = |val| Arc::new(Node { val, children: vec![] });
+/// let mid = Arc::new(Node { val: 1, children: vec![leaf(2), leaf(3)] });
+/// let root = CowCell::new(Node { val: 0, children: vec![mid, leaf(4)] });
+///
+/// let mut borrow = root.borrow();
+/// let mut mid = borrow.descend(|n| &n.children[0], |n| &mut n.children[0]);
+/// let mut leaf = mid.descend(|n| &n.children[1], |n| &mut n.children[1]);
+/// leaf.val = 30;
+/// leaf.commit();
+/// mid.commit();
+///
+/// // Only the path from the root to the leaf has been copied.
+/// let old = root.borrow();
+/// assert_eq!(borrow.children[0].children[1].val, 30);
+/// assert!(Arc::ptr_eq(&borrow.children[1], &old.children[1]));
+/// assert!(Arc::ptr_eq(
+///     &borrow.children[0].children[0],
+///     &old.children[0].children[0],
+/// ));
+/// ```
+pub struct PathRef<'p, P: CowLayer, U, F, G> {
+	parent: &'p mut P,
+	get: F,
+	get_mut: G,
+	copy: Option<U>,
+}
+
+impl<'a, T: Clone, C: CowClone<T, Owned = T>> CowRef<'a, T, C> {
+	/// Create a borrow of the node behind the [`Arc`] returned by
+	/// `get`, which commits into a copy of this borrow. `get` and
+	/// `get_mut` must return the same [`Arc`].
+	///
+	/// See [`PathRef`] for when cloning happens.
+	#[inline]
+	pub fn descend<U, F, G>(
+		&mut self,
+		get: F,
+		get_mut: G,
+	) -> PathRef<'_, Self, U, F, G>
+	where
+		U: Clone,
+		F: Fn(&T) -> &Arc<U>,
+		G: FnMut(&mut T) -> &mut Arc<U>,
+	{
+		PathRef::new(self, get, get_mut)
+	}
+}
+
+impl<'p, P, U, F, G> PathRef<'p, P, U, F, G>
+where
+	P: CowLayer,
+	U: Clone,
+	F: Fn(&P::Target) -> &Arc<U>,
+	G: FnMut(&mut P::Target) -> &mut Arc<U>,
+{
+	/// A new borrow of the node behind the [`Arc`] returned by `get`
+	/// from the value of `parent`.
+	#[inline]
+	pub fn new(parent: &'p mut P, get: F, get_mut: G) -> Self {
+		Self {
+			parent,
+			get,
+			get_mut,
+			copy: None,
+		}
+	}
+
+	/// Returns a reference to the parent of this borrow.
+	#[inline]
+	pub fn get_parent(&self) -> &P {
+		self.parent
+	}
+
+	/// Get an immutable reference to the node.
+	#[inline]
+	pub fn get_ref(&self) -> &U {
+		match self.copy.as_ref() {
+			Some(v) => v,
+			None => (self.get)(self.parent.get_ref()),
+		}
+	}
+
+	/// Returns [`true`] if this [`PathRef`] has made a copy of the
+	/// node.
+	#[inline]
+	pub const fn is_cloned(&self) -> bool {
+		self.copy.is_some()
+	}
+
+	/// Get a mutable reference to the node, cloning it if necessary.
+	/// The children of the node are shared with the original.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut U {
+		if self.copy.is_none() {
+			self.copy = Some(self.get_ref().clone());
+		}
+		self.copy.as_mut().unwrap()
+	}
+
+	/// Consume the [`PathRef`], retrieving the node. This clones the
+	/// node if a copy was not already made.
+	#[inline]
+	pub fn into_inner(self) -> U {
+		match self.copy {
+			Some(v) => v,
+			None => self.get_ref().clone(),
+		}
+	}
+
+	/// Replace the node in a copy of the parent with the copy made
+	/// by this borrow, if any.
+	pub fn commit(mut self) {
+		if let Some(copy) = self.copy {
+			*(self.get_mut)(self.parent.get_mut()) = Arc::new(copy);
+		}
+	}
+
+	/// Create a borrow of a child of this node.
+	#[inline]
+	pub fn descend<V, F2, G2>(
+		&mut self,
+		get: F2,
+		get_mut: G2,
+	) -> PathRef<'_, Self, V, F2, G2>
+	where
+		V: Clone,
+		F2: Fn(&U) -> &Arc<V>,
+		G2: FnMut(&mut U) -> &mut Arc<V>,
+	{
+		PathRef::new(self, get, get_mut)
+	}
+
+	/// Create a child borrow of this one.
+	#[inline]
+	pub fn child(&mut self) -> ChildRef<'_, Self> {
+		ChildRef::new(self)
+	}
+}
+
+impl<P, U, F, G> CowLayer for PathRef<'_, P, U, F, G>
+where
+	P: CowLayer,
+	U: Clone,
+	F: Fn(&P::Target) -> &Arc<U>,
+	G: FnMut(&mut P::Target) -> &mut Arc<U>,
+{
+	type Target = U;
+
+	#[inline]
+	fn get_ref(&self) -> &U {
+		PathRef::get_ref(self)
+	}
+
+	#[inline]
+	fn get_mut(&mut self) -> &mut U {
+		PathRef::get_mut(self)
+	}
+
+	#[inline]
+	fn set(&mut self, val: U) {
+		self.copy = Some(val);
+	}
+}
+
+impl<P, U, F, G> Deref for PathRef<'_, P, U, F, G>
+where
+	P: CowLayer,
+	U: Clone,
+	F: Fn(&P::Target) -> &Arc<U>,
+	G: FnMut(&mut P::Target) -> &mut Arc<U>,
+{
+	type Target = U;
+
+	#[inline]
+	fn deref(&self) -> &U {
+		self.get_ref()
+	}
+}
+
+impl<P, U, F, G> DerefMut for PathRef<'_, P, U, F, G>
+where
+	P: CowLayer,
+	U: Clone,
+	F: Fn(&P::Target) -> &Arc<U>,
+	G: FnMut(&mut P::Target) -> &mut Arc<U>,
+{
+	#[inline]
+	fn deref_mut(&mut self) -> &mut U {
+		self.get_mut()
+	}
+}
+
+impl<P, U, F, G> fmt::Debug for PathRef<'_, P, U, F, G>
+where
+	P: CowLayer,
+	U: Clone + fmt::Debug,
+	F: Fn(&P::Target) -> &Arc<U>,
+	G: FnMut(&mut P::Target) -> &mut Arc<U>,
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("PathRef")
+			.field("val", self.get_ref())
+			.field("is_cloned", &self.is_cloned())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::CowCell;
+	use alloc::sync::Arc;
+
+	#[test]
+	fn uncommitted_path() {
+		let cell = CowCell::new((Arc::new(0), Arc::new(1)));
+		let mut borrow = cell.borrow();
+		{
+			let mut node = borrow.descend(|t| &t.0, |t| &mut t.0);
+			*node += 1;
+			assert_eq!(*node, 1);
+		}
+		assert!(!borrow.is_cloned());
+
+		let mut node = borrow.descend(|t| &t.1, |t| &mut t.1);
+		let mut child = node.child();
+		*child += 1;
+		child.commit();
+		node.commit();
+		assert_eq!((*borrow.0, *borrow.1), (0, 2));
+		assert!(Arc::ptr_eq(&borrow.0, &cell.borrow().0));
+	}
+}
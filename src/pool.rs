@@ -0,0 +1,166 @@
+//! Recycling the copies of borrows.
+
+use crate::state::BorrowState;
+use crate::{CowClone, CowCopy};
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::fmt;
+
+/// A pool of retired copies, reused by later borrows to avoid
+/// allocating new ones.
+///
+/// A `&CowPool<T>` is a [`CowClone`] strategy: borrows made with
+/// [`CowCell::borrow_with`] take a retired copy from the pool and
+/// fill it with [`Clone::clone_from`], which reuses its allocations.
+/// Copies that are dropped with their borrow, as well as the values
+/// replaced by a commit, are returned to the pool, up to its maximum
+/// size.
+///
+/// ```rust
+/// use cowcell::{CowCell, CowPool};
+///
+/// let cell = CowCell::new(vec![0u8; 1024]);
+/// let pool = CowPool::new(4);
+///
+/// let mut borrow = cell.borrow_with(&pool);
+/// borrow[0] = 1;
+/// let ptr = borrow.as_ptr();
+/// drop(borrow);
+/// assert_eq!(pool.len(), 1);
+///
+/// // The next copy reuses the buffer of the previous one.
+/// let mut borrow = cell.borrow_with(&pool);
+/// borrow[0] = 2;
+/// assert_eq!(borrow.as_ptr(), ptr);
+/// assert_eq!(borrow[..2], [2, 0]);
+/// ```
+///
+/// [`CowCell::borrow_with`]: crate::CowCell::borrow_with
+pub struct CowPool<T> {
+	state: BorrowState,
+	free: UnsafeCell<Vec<T>>,
+	max: usize,
+}
+
+// SAFETY: the free list is only accessed under a write, which
+// `BorrowState` makes exclusive across threads. Values of `T` are
+// moved between threads through the pool.
+unsafe impl<T: Send> Sync for CowPool<T> {}
+
+impl<T> CowPool<T> {
+	/// Create an empty pool keeping up to `max` retired copies.
+	#[inline]
+	pub const fn new(max: usize) -> Self {
+		Self {
+			state: BorrowState::new(),
+			free: UnsafeCell::new(Vec::new()),
+			max,
+		}
+	}
+
+	/// Run `f` on the free list with exclusive access to it.
+	#[inline]
+	fn with_free<R>(&self, f: impl FnOnce(&mut Vec<T>) -> R) -> R {
+		let _write = self.state.write();
+		// SAFETY: the write guard excludes every other access.
+		f(unsafe { &mut *self.free.get() })
+	}
+
+	/// Returns the number of retired copies in the pool.
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.with_free(|free| free.len())
+	}
+
+	/// Returns [`true`] if the pool holds no retired copies.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Take a retired copy out of the pool.
+	#[inline]
+	pub fn take(&self) -> Option<T> {
+		self.with_free(Vec::pop)
+	}
+
+	/// Return a copy to the pool. It is dropped if the pool is full.
+	pub fn put(&self, val: T) {
+		let rejected = self.with_free(|free| {
+			if free.len() < self.max {
+				free.push(val);
+				None
+			} else {
+				Some(val)
+			}
+		});
+		// Drop the value outside of the write, in case its
+		// destructor uses the pool.
+		drop(rejected);
+	}
+}
+
+impl<T> CowCopy<T> for &CowPool<T> {
+	type Owned = T;
+
+	#[inline]
+	fn recycle(&mut self, copy: T) {
+		self.put(copy);
+	}
+}
+
+impl<T: Clone> CowClone<T> for &CowPool<T> {
+	#[inline]
+	fn cow_clone(&mut self, val: &T) -> T {
+		match self.take() {
+			Some(mut copy) => {
+				copy.clone_from(val);
+				copy
+			}
+			None => val.clone(),
+		}
+	}
+}
+
+impl<T> Default for CowPool<T> {
+	#[inline]
+	fn default() -> Self {
+		Self::new(usize::MAX)
+	}
+}
+
+impl<T> fmt::Debug for CowPool<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("CowPool")
+			.field("len", &self.len())
+			.field("max", &self.max)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::CowCell;
+
+	#[test]
+	fn commit_recycles_old_value() {
+		let cell = CowCell::new(vec![1, 2]);
+		let pool = CowPool::new(1);
+		let mut borrow = cell.borrow_with(&pool);
+		borrow.push(3);
+		borrow.commit().unwrap();
+		assert_eq!(pool.take(), Some(vec![1, 2]));
+		assert_eq!(*cell.borrow(), [1, 2, 3]);
+	}
+
+	#[test]
+	fn bounded() {
+		let pool = CowPool::new(1);
+		pool.put(1);
+		pool.put(2);
+		assert_eq!(pool.len(), 1);
+		assert_eq!(pool.take(), Some(1));
+		assert!(pool.is_empty());
+	}
+}
@@ -0,0 +1,220 @@
+//! Clone-on-write views of a single field of a borrow.
+
+use crate::CowRef;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+/// A clone-on-write view of a field of the value behind a [`CowRef`],
+/// created with [`CowRef::project`].
+///
+/// Reading through the projection reads the field of the parent
+/// borrow, which is zero-cost against the original value if the
+/// parent has not made a copy yet. The first write clones only the
+/// field into a private copy held by the projection, whether the
+/// parent has made a copy or not. The parent is left untouched until
+/// [`Projection::commit`] writes the field back into it, so dropping
+/// the projection without committing always discards its writes.
+/// Committing into a parent that has not made a copy yet clones the
+/// whole value, old field included, before replacing the field.
+///
+/// The accessors can be any closures, including ones that capture
+/// state such as an index.
+///
+/// ```rust
+/// use cowcell::CowCell;
+///
+/// struct Config {
+///     name: String,
+///     data: Vec<u8>,
+/// }
+///
+/// let cell = CowCell::new(Config {
+///     name: "a".into(),
+///     data: vec![0; 4096],
+/// });
+/// let mut borrow = cell.borrow();
+/// let mut name = borrow.project(|c| &c.name, |c| &mut c.name);
+///
+/// // Only the name is cloned.
+/// name.push('b');
+/// assert_eq!(*name, "ab");
+/// assert!(name.is_cloned());
+/// assert_eq!(name.into_inner(), "ab");
+/// assert!(!borrow.is_cloned());
+/// ```
+pub struct Projection<'r, 'a, T, U, F, G> {
+	parent: &'r mut CowRef<'a, T>,
+	get: F,
+	get_mut: G,
+	copy: Option<U>,
+}
+
+impl<'a, T> CowRef<'a, T> {
+	/// Create a clone-on-write view of a field of this borrow. `get`
+	/// and `get_mut` must return the same field.
+	///
+	/// See [`Projection`] for when cloning happens.
+	#[inline]
+	pub fn project<U, F, G>(
+		&mut self,
+		get: F,
+		get_mut: G,
+	) -> Projection<'_, 'a, T, U, F, G>
+	where
+		F: Fn(&T) -> &U,
+		G: FnMut(&mut T) -> &mut U,
+	{
+		Projection {
+			parent: self,
+			get,
+			get_mut,
+			copy: None,
+		}
+	}
+}
+
+impl<'a, T, U, F, G> Projection<'_, 'a, T, U, F, G>
+where
+	F: Fn(&T) -> &U,
+{
+	/// Returns a reference to the borrow this projection was created
+	/// from.
+	#[inline]
+	pub fn get_parent(&self) -> &CowRef<'a, T> {
+		self.parent
+	}
+
+	/// Get an immutable reference to the field.
+	#[inline]
+	pub fn get_ref(&self) -> &U {
+		match self.copy.as_ref() {
+			Some(v) => v,
+			None => (self.get)(self.parent.get_ref()),
+		}
+	}
+
+	/// Returns [`true`] if this [`Projection`] has made a private copy
+	/// of the field.
+	#[inline]
+	pub const fn is_cloned(&self) -> bool {
+		self.copy.is_some()
+	}
+}
+
+impl<T, U, F, G> Projection<'_, '_, T, U, F, G>
+where
+	U: Clone,
+	F: Fn(&T) -> &U,
+{
+	/// Get a mutable reference to the field, cloning it if
+	/// necessary.
+	pub fn get_mut(&mut self) -> &mut U {
+		if self.copy.is_none() {
+			self.copy = Some(self.get_ref().clone());
+		}
+		self.copy.as_mut().unwrap()
+	}
+
+	/// Consume the [`Projection`], retrieving the field. This clones
+	/// the field if a copy was not already made.
+	#[inline]
+	pub fn into_inner(self) -> U {
+		match self.copy {
+			Some(v) => v,
+			None => (self.get)(self.parent.get_ref()).clone(),
+		}
+	}
+}
+
+impl<T, U, F, G> Projection<'_, '_, T, U, F, G>
+where
+	T: Clone,
+	G: FnMut(&mut T) -> &mut U,
+{
+	/// Write the private copy of the field, if any, back into the
+	/// parent borrow, cloning the parent value if necessary.
+	#[inline]
+	pub fn commit(mut self) {
+		if let Some(copy) = self.copy {
+			*(self.get_mut)(self.parent.get_mut()) = copy;
+		}
+	}
+}
+
+impl<T, U, F, G> fmt::Debug for Projection<'_, '_, T, U, F, G>
+where
+	T: fmt::Debug,
+	U: fmt::Debug,
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Projection")
+			.field("parent", &self.parent)
+			.field("copy", &self.copy)
+			.finish_non_exhaustive()
+	}
+}
+
+impl<T, U, F, G> Deref for Projection<'_, '_, T, U, F, G>
+where
+	F: Fn(&T) -> &U,
+{
+	type Target = U;
+
+	#[inline]
+	fn deref(&self) -> &Self::Target {
+		self.get_ref()
+	}
+}
+
+impl<T, U, F, G> DerefMut for Projection<'_, '_, T, U, F, G>
+where
+	U: Clone,
+	F: Fn(&T) -> &U,
+{
+	#[inline]
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.get_mut()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::CowCell;
+
+	#[test]
+	fn commit_into_parent() {
+		let cell = CowCell::new((1, vec![2]));
+		let mut borrow = cell.borrow();
+		let mut second = borrow.project(|v| &v.1, |v| &mut v.1);
+		second.push(3);
+		second.commit();
+		assert_eq!(*borrow, (1, vec![2, 3]));
+		assert_eq!(*cell.borrow(), (1, vec![2]));
+	}
+
+	#[test]
+	fn drop_discards_writes() {
+		let cell = CowCell::new((1, vec![2]));
+		let mut borrow = cell.borrow();
+		borrow.project(|v| &v.1, |v| &mut v.1).push(3);
+		assert!(!borrow.is_cloned());
+
+		borrow.0 = 4;
+		let mut second = borrow.project(|v| &v.1, |v| &mut v.1);
+		second.push(3);
+		assert!(second.is_cloned());
+		drop(second);
+		assert_eq!(*borrow, (4, vec![2]));
+	}
+
+	#[test]
+	fn capturing_accessors() {
+		let cell = CowCell::new(vec![vec![1], vec![2]]);
+		let mut borrow = cell.borrow();
+		let i = 1;
+		let mut item = borrow.project(|v| &v[i], |v| &mut v[i]);
+		item.push(3);
+		item.commit();
+		assert_eq!(*borrow, [vec![1], vec![2, 3]]);
+	}
+}
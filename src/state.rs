@@ -0,0 +1,128 @@
+//! Borrow tracking for the cells in this crate.
+
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// State value while a writer has exclusive access.
+const WRITING: usize = usize::MAX;
+
+/// A reader count with an exclusive writer state.
+///
+/// Readers never block each other. A writer can only get in while
+/// there are no readers, and readers that arrive during a write spin
+/// until it is done. Writes only swap a value in place, so they are
+/// expected to be short.
+#[derive(Debug)]
+pub(crate) struct BorrowState(AtomicUsize);
+
+impl BorrowState {
+	pub(crate) const fn new() -> Self {
+		Self(AtomicUsize::new(0))
+	}
+
+	/// Register a new reader, waiting for any writer to finish.
+	pub(crate) fn read(&self) -> ReadGuard<'_> {
+		let mut cur = self.0.load(Ordering::Relaxed);
+		loop {
+			if cur == WRITING {
+				spin_loop();
+				cur = self.0.load(Ordering::Relaxed);
+				continue;
+			}
+			assert!(cur < WRITING - 1, "too many borrows");
+			match self.0.compare_exchange_weak(
+				cur,
+				cur + 1,
+				Ordering::Acquire,
+				Ordering::Relaxed,
+			) {
+				Ok(_) => return ReadGuard(self),
+				Err(next) => cur = next,
+			}
+		}
+	}
+
+	/// Try to get exclusive access. Fails if there are readers or
+	/// another writer.
+	pub(crate) fn try_write(&self) -> Option<WriteGuard<'_>> {
+		self.0
+			.compare_exchange(
+				0,
+				WRITING,
+				Ordering::Acquire,
+				Ordering::Relaxed,
+			)
+			.ok()
+			.map(|_| WriteGuard(self))
+	}
+
+	/// Get exclusive access, waiting for readers and other writers
+	/// to finish. Only for cells whose readers are short-lived.
+	#[cfg(feature = "alloc")]
+	pub(crate) fn write(&self) -> WriteGuard<'_> {
+		loop {
+			if let Some(guard) = self.try_write() {
+				return guard;
+			}
+			spin_loop();
+		}
+	}
+}
+
+/// A registered reader, released on drop.
+#[derive(Debug)]
+pub(crate) struct ReadGuard<'a>(&'a BorrowState);
+
+impl Drop for ReadGuard<'_> {
+	#[inline]
+	fn drop(&mut self) {
+		self.0 .0.fetch_sub(1, Ordering::Release);
+	}
+}
+
+impl ReadGuard<'_> {
+	/// Try to get exclusive access while keeping this read, which
+	/// comes back when the returned guard is dropped. Fails if there
+	/// are other readers.
+	#[cfg(feature = "alloc")]
+	pub(crate) fn try_upgrade_in_place(
+		&mut self,
+	) -> Option<UpgradeGuard<'_>> {
+		let state = self.0;
+		state
+			.0
+			.compare_exchange(
+				1,
+				WRITING,
+				Ordering::Acquire,
+				Ordering::Relaxed,
+			)
+			.ok()
+			.map(|_| UpgradeGuard(state))
+	}
+}
+
+/// Exclusive access upgraded from a read, turned back into the read
+/// on drop.
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub(crate) struct UpgradeGuard<'a>(&'a BorrowState);
+
+#[cfg(feature = "alloc")]
+impl Drop for UpgradeGuard<'_> {
+	#[inline]
+	fn drop(&mut self) {
+		self.0 .0.store(1, Ordering::Release);
+	}
+}
+
+/// Exclusive access, released on drop.
+#[derive(Debug)]
+pub(crate) struct WriteGuard<'a>(&'a BorrowState);
+
+impl Drop for WriteGuard<'_> {
+	#[inline]
+	fn drop(&mut self) {
+		self.0 .0.store(0, Ordering::Release);
+	}
+}
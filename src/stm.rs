@@ -0,0 +1,284 @@
+//! Transactions committing borrows of several cells atomically.
+
+use crate::state::WriteGuard;
+use crate::{CommitError, CowCell, CowRef};
+use core::sync::atomic;
+
+/// A tuple of distinct cells that can be borrowed and committed
+/// together in a transaction.
+///
+/// This is implemented for tuples of up to four `&CowCell`s, and with
+/// the `alloc` feature for tuples of up to four `&SyncCowCell`s.
+///
+/// For `CowCell`s, the borrows that made no copy form the read set of
+/// the transaction, and keep reading their cell until the commit, so
+/// that no other commit can change it. The borrows that made a copy
+/// form its write set, and are committed only if none of their cells
+/// changed since they were borrowed.
+///
+/// For `SyncCowCell`s, readers never block writers, so the snapshots
+/// of every borrow are checked to still be current. Transactions take
+/// a global commit lock, so that they never wait on each other while
+/// holding the lock of a cell.
+pub trait TxCells<'a> {
+	/// The borrows of the cells.
+	type Refs;
+	/// The copies made by the borrows, handed back when the commit
+	/// is rejected.
+	type Copies;
+
+	/// Borrow every cell.
+	///
+	/// # Panics
+	///
+	/// If the same cell appears more than once.
+	fn begin(&self) -> Self::Refs;
+
+	/// Commit the copies made by the borrows, all at once.
+	///
+	/// The commit is rejected, leaving every cell untouched, if a
+	/// cell written to is being read by other borrows
+	/// ([`CommitError::Busy`]), or if a cell was committed to since
+	/// it was borrowed ([`CommitError::Conflict`]).
+	fn commit(
+		refs: Self::Refs,
+	) -> Result<(), CommitError<Self::Copies>>;
+}
+
+/// Run `f` on borrows of every cell in `cells`, and commit all their
+/// copies atomically. Whenever a cell changed concurrently, `f` runs
+/// again on new borrows, so it may be called more than once.
+///
+/// Commits rejected because other borrows are reading a cell written
+/// to are not retried, and return [`CommitError::Busy`].
+///
+/// ```rust
+/// use cowcell::{transact, CowCell};
+///
+/// let checking = CowCell::new(100);
+/// let savings = CowCell::new(0);
+/// transact((&checking, &savings), |(from, to)| {
+///     **from -= 30;
+///     **to += 30;
+/// })
+/// .unwrap();
+/// assert_eq!((*checking.borrow(), *savings.borrow()), (70, 30));
+/// ```
+pub fn transact<'a, C, R, F>(
+	cells: C,
+	mut f: F,
+) -> Result<R, CommitError<C::Copies>>
+where
+	C: TxCells<'a>,
+	F: FnMut(&mut C::Refs) -> R,
+{
+	loop {
+		let mut refs = cells.begin();
+		let res = f(&mut refs);
+		match C::commit(refs) {
+			Ok(()) => return Ok(res),
+			Err(CommitError::Conflict(_)) => continue,
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+/// Panic if any two of `cells` are the same.
+fn assert_distinct(cells: &[*const ()]) {
+	for (i, a) in cells.iter().enumerate() {
+		assert!(
+			!cells[i + 1..].contains(a),
+			"cells of a transaction must be distinct"
+		);
+	}
+}
+
+/// Try to get exclusive access to the cell of `borrow` if it made a
+/// copy. Returns `Some(None)` if it made none.
+#[inline]
+fn try_lock<'a, T: Clone>(
+	borrow: &CowRef<'a, T>,
+) -> Option<Option<WriteGuard<'a>>> {
+	if !borrow.is_cloned() {
+		return Some(None);
+	}
+	borrow.ptr.state.try_write().map(Some)
+}
+
+/// Returns [`true`] if `borrow` made no copy, or if its cell was not
+/// committed to since it was made.
+#[inline]
+fn is_current<T: Clone>(borrow: &CowRef<'_, T>) -> bool {
+	!borrow.is_cloned() || borrow.ptr.version() == borrow.version
+}
+
+/// Publish the copy made by `borrow`, if any, returning the value it
+/// replaces.
+///
+/// # Safety
+///
+/// The caller must hold exclusive access to the cell of `borrow`.
+#[inline]
+unsafe fn publish<T: Clone>(borrow: &mut CowRef<'_, T>) -> Option<T> {
+	let copy = borrow.copy.take()?;
+	let old = core::mem::replace(&mut *borrow.ptr.val.get(), copy);
+	borrow.ptr.version.store(
+		borrow.version.wrapping_add(1),
+		atomic::Ordering::Release,
+	);
+	Some(old)
+}
+
+macro_rules! impl_tx_cells {
+	($($t:ident $i:tt),+) => {
+		impl<'a, $($t: Clone),+> TxCells<'a> for ($(&'a CowCell<$t>,)+) {
+			type Refs = ($(CowRef<'a, $t>,)+);
+			type Copies = ($(Option<$t>,)+);
+
+			fn begin(&self) -> Self::Refs {
+				assert_distinct(&[$(self.$i as *const _ as *const ()),+]);
+				($(self.$i.borrow(),)+)
+			}
+
+			fn commit(
+				mut refs: Self::Refs,
+			) -> Result<(), CommitError<Self::Copies>> {
+				let locks = ($(try_lock(&refs.$i),)+);
+				if false $(|| locks.$i.is_none())+ {
+					drop(locks);
+					let copies = ($(refs.$i.copy.take(),)+);
+					return Err(CommitError::Busy(copies));
+				}
+				if !(true $(&& is_current(&refs.$i))+) {
+					drop(locks);
+					let copies = ($(refs.$i.copy.take(),)+);
+					return Err(CommitError::Conflict(copies));
+				}
+				// SAFETY: we hold exclusive access to every cell with
+				// a copy.
+				let old = unsafe { ($(publish(&mut refs.$i),)+) };
+				// Drop the old values outside of the writes, in case
+				// their destructors use the cells.
+				drop(locks);
+				drop(old);
+				Ok(())
+			}
+		}
+
+		#[cfg(feature = "alloc")]
+		impl<'a, $($t: Clone),+> TxCells<'a>
+			for ($(&'a crate::SyncCowCell<$t>,)+)
+		{
+			type Refs = ($(crate::SyncCowRef<'a, $t>,)+);
+			type Copies = ($(Option<$t>,)+);
+
+			fn begin(&self) -> Self::Refs {
+				assert_distinct(&[$(self.$i as *const _ as *const ()),+]);
+				($(self.$i.borrow(),)+)
+			}
+
+			fn commit(
+				mut refs: Self::Refs,
+			) -> Result<(), CommitError<Self::Copies>> {
+				let global = sync::COMMIT_LOCK.write();
+				let locks = ($(refs.$i.lock(),)+);
+				// SAFETY: we hold the lock of every cell.
+				if !unsafe { true $(&& refs.$i.is_current())+ } {
+					drop(locks);
+					drop(global);
+					let copies = ($(refs.$i.take_copy(),)+);
+					return Err(CommitError::Conflict(copies));
+				}
+				// SAFETY: we hold the lock of every cell.
+				let old = unsafe { ($(refs.$i.publish_locked(),)+) };
+				drop(locks);
+				drop(global);
+				drop(old);
+				Ok(())
+			}
+		}
+	};
+}
+
+#[cfg(feature = "alloc")]
+mod sync {
+	use crate::state::BorrowState;
+
+	/// Serializes the commits of transactions over `SyncCowCell`s.
+	pub(super) static COMMIT_LOCK: BorrowState = BorrowState::new();
+}
+
+impl_tx_cells!(A 0);
+impl_tx_cells!(A 0, B 1);
+impl_tx_cells!(A 0, B 1, C 2);
+impl_tx_cells!(A 0, B 1, C 2, D 3);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(feature = "alloc")]
+	use crate::SyncCowCell;
+
+	#[test]
+	fn read_set_blocks_commits() {
+		let a = CowCell::new(1);
+		let b = CowCell::new(2);
+		let mut refs = (&a, &b).begin();
+		*refs.1 += *refs.0;
+		let mut writer = a.borrow();
+		*writer = 10;
+		assert!(writer.commit().is_err());
+		<(&CowCell<_>, &CowCell<_>)>::commit(refs).unwrap();
+		assert_eq!(*b.borrow(), 3);
+	}
+
+	#[test]
+	fn conflict_retries() {
+		let a = CowCell::new(0);
+		let b = CowCell::new(0);
+		let mut runs = 0;
+		transact((&a, &b), |(x, y)| {
+			runs += 1;
+			**y += 1;
+			if runs == 1 {
+				// Another commit lands in the middle of the
+				// transaction.
+				let mut other = b.borrow();
+				*other = 10;
+				other.commit().unwrap();
+			}
+			**x += 1;
+		})
+		.unwrap();
+		assert_eq!(runs, 2);
+		assert_eq!((*a.borrow(), *b.borrow()), (1, 11));
+	}
+
+	#[test]
+	#[should_panic = "must be distinct"]
+	fn same_cell_twice() {
+		let a = CowCell::new(0);
+		(&a, &a).begin();
+	}
+
+	#[test]
+	#[cfg(feature = "alloc")]
+	fn sync_transfers() {
+		let a = SyncCowCell::new(1000u32);
+		let b = SyncCowCell::new(0u32);
+		std::thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..100 {
+						transact((&a, &b), |(x, y)| {
+							**x -= 1;
+							**y += 1;
+						})
+						.unwrap();
+					}
+				});
+			}
+		});
+		assert_eq!((*a.load(), *b.load()), (600, 400));
+	}
+}
@@ -0,0 +1,323 @@
+//! A thread-safe cell publishing immutable snapshots.
+
+use crate::state::{BorrowState, WriteGuard};
+use crate::CommitError;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+/// A thread-safe cell that publishes its value as [`Arc`] snapshots,
+/// in the style of RCU.
+///
+/// Readers get a [`SyncCowRef`] over the snapshot that was current
+/// when they borrowed, and keep it alive for as long as they need,
+/// without blocking writers. Writers clone a snapshot, modify the
+/// copy and publish it atomically as the new version of the cell.
+///
+/// ```rust
+/// use cowcell::SyncCowCell;
+///
+/// let cell = SyncCowCell::new(vec![1, 2]);
+/// let reader = cell.borrow();
+///
+/// std::thread::scope(|s| {
+///     s.spawn(|| cell.update(|v| v.push(3)));
+/// });
+///
+/// // The reader still sees the snapshot it borrowed.
+/// assert_eq!(*reader, [1, 2]);
+/// assert_eq!(*cell.borrow(), [1, 2, 3]);
+/// ```
+pub struct SyncCowCell<T> {
+	state: BorrowState,
+	snap: UnsafeCell<Arc<T>>,
+}
+
+// SAFETY: the snapshot is only replaced under a write, which
+// `BorrowState` makes exclusive across threads.
+unsafe impl<T: Send + Sync> Sync for SyncCowCell<T> {}
+
+impl<T> SyncCowCell<T> {
+	/// Create a new [`SyncCowCell`] containing the given value.
+	#[inline]
+	pub fn new(val: T) -> Self {
+		Self::from_arc(Arc::new(val))
+	}
+
+	/// Create a new [`SyncCowCell`] whose first snapshot is the
+	/// given [`Arc`].
+	#[inline]
+	pub const fn from_arc(snap: Arc<T>) -> Self {
+		Self {
+			state: BorrowState::new(),
+			snap: UnsafeCell::new(snap),
+		}
+	}
+
+	/// Get the current snapshot.
+	pub fn load(&self) -> Arc<T> {
+		let _read = self.state.read();
+		// SAFETY: the read excludes writers.
+		unsafe { Arc::clone(&*self.snap.get()) }
+	}
+
+	/// Create a new borrow with copy-on-write semantics over the
+	/// current snapshot.
+	#[inline]
+	pub fn borrow(&self) -> SyncCowRef<'_, T> {
+		SyncCowRef {
+			cell: self,
+			snap: self.load(),
+			copy: None,
+		}
+	}
+
+	/// Publish a new value, regardless of the current one.
+	pub fn store(&self, val: T) {
+		let new = Arc::new(val);
+		let write = self.state.write();
+		// SAFETY: the write guard excludes every other access.
+		let old =
+			unsafe { core::mem::replace(&mut *self.snap.get(), new) };
+		drop(write);
+		drop(old);
+	}
+
+	/// Publish `new` if `base` is still the current snapshot.
+	fn publish(
+		&self,
+		base: &Arc<T>,
+		new: T,
+	) -> Result<(), CommitError<T>> {
+		let write = self.state.write();
+		// SAFETY: the write guard excludes every other access.
+		let snap = unsafe { &mut *self.snap.get() };
+		if !Arc::ptr_eq(snap, base) {
+			return Err(CommitError::Conflict(new));
+		}
+		let old = core::mem::replace(snap, Arc::new(new));
+		drop(write);
+		drop(old);
+		Ok(())
+	}
+
+	/// Consume the [`SyncCowCell`], retrieving the latest snapshot.
+	#[inline]
+	pub fn into_arc(self) -> Arc<T> {
+		self.snap.into_inner()
+	}
+}
+
+impl<T: Clone> SyncCowCell<T> {
+	/// Modify the value with `f` and publish the result. If another
+	/// writer publishes in the meantime, `f` runs again on the new
+	/// snapshot, so it may be called more than once.
+	pub fn update<F: FnMut(&mut T)>(&self, mut f: F) {
+		loop {
+			let mut borrow = self.borrow();
+			f(borrow.get_mut());
+			if borrow.commit().is_ok() {
+				return;
+			}
+		}
+	}
+
+	/// Get a mutable reference to the latest value, cloning it if
+	/// readers still hold the snapshot.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		Arc::make_mut(self.snap.get_mut())
+	}
+
+	/// Consume the [`SyncCowCell`], retrieving the latest value. This
+	/// clones the value if readers still hold the snapshot.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		Arc::unwrap_or_clone(self.into_arc())
+	}
+}
+
+impl<T: Default> Default for SyncCowCell<T> {
+	#[inline]
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T: fmt::Debug> fmt::Debug for SyncCowCell<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SyncCowCell")
+			.field("val", &self.load())
+			.finish()
+	}
+}
+
+impl<T> From<T> for SyncCowCell<T> {
+	#[inline]
+	fn from(val: T) -> Self {
+		Self::new(val)
+	}
+}
+
+/// A borrow from a [`SyncCowCell`] with clone-on-write semantics on
+/// mutable access.
+///
+/// This type provides immutable access to the snapshot that was
+/// current when the borrow was made, even if newer versions are
+/// published afterwards. When accessed mutably, it clones the
+/// snapshot into a private copy, which can then be published with
+/// [`SyncCowRef::commit`].
+#[derive(Debug)]
+pub struct SyncCowRef<'a, T> {
+	cell: &'a SyncCowCell<T>,
+	snap: Arc<T>,
+	copy: Option<T>,
+}
+
+impl<'a, T> SyncCowRef<'a, T> {
+	/// Returns a reference to the [`SyncCowCell`] that originated
+	/// this borrow.
+	#[inline]
+	pub const fn get_cell(&self) -> &'a SyncCowCell<T> {
+		self.cell
+	}
+
+	/// Returns the snapshot this borrow was made from.
+	#[inline]
+	pub const fn snapshot(&self) -> &Arc<T> {
+		&self.snap
+	}
+
+	/// Get an immutable reference to the inner value.
+	#[inline]
+	pub fn get_ref(&self) -> &T {
+		match self.copy.as_ref() {
+			Some(v) => v,
+			None => &self.snap,
+		}
+	}
+
+	/// Returns [`true`] if this [`SyncCowRef`] has made a copy of the
+	/// snapshot.
+	#[inline]
+	pub const fn is_cloned(&self) -> bool {
+		self.copy.is_some()
+	}
+
+	/// Publish the copy made by this borrow as the new version of the
+	/// originating [`SyncCowCell`].
+	///
+	/// If no copy was made there is nothing to publish, and this
+	/// returns `Ok`. If another version was published after this
+	/// borrow was made, the cell is left untouched and the copy is
+	/// handed back in [`CommitError::Conflict`].
+	pub fn commit(self) -> Result<(), CommitError<T>> {
+		match self.copy {
+			Some(copy) => self.cell.publish(&self.snap, copy),
+			None => Ok(()),
+		}
+	}
+}
+
+impl<'a, T> SyncCowRef<'a, T> {
+	/// Wait for exclusive access to the originating cell.
+	#[inline]
+	pub(crate) fn lock(&self) -> WriteGuard<'a> {
+		self.cell.state.write()
+	}
+
+	/// Returns [`true`] if the snapshot of this borrow is still the
+	/// current one. The caller must hold the lock of the cell.
+	#[inline]
+	pub(crate) unsafe fn is_current(&self) -> bool {
+		Arc::ptr_eq(&*self.cell.snap.get(), &self.snap)
+	}
+
+	/// Publish the copy made by this borrow, if any, returning the
+	/// snapshot it replaces. The caller must hold the lock of the
+	/// cell.
+	#[inline]
+	pub(crate) unsafe fn publish_locked(&mut self) -> Option<Arc<T>> {
+		let copy = self.copy.take()?;
+		Some(core::mem::replace(
+			&mut *self.cell.snap.get(),
+			Arc::new(copy),
+		))
+	}
+
+	/// Take the copy made by this borrow.
+	#[inline]
+	pub(crate) fn take_copy(&mut self) -> Option<T> {
+		self.copy.take()
+	}
+}
+
+impl<'a, T: Clone> SyncCowRef<'a, T> {
+	/// Get a mutable reference to the inner value, cloning the
+	/// snapshot if necessary.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		let snap = &self.snap;
+		self.copy.get_or_insert_with(|| T::clone(snap))
+	}
+
+	/// Consume the [`SyncCowRef`], retrieving the inner value. This
+	/// clones the snapshot if a copy was not already made and other
+	/// references to it exist.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		match self.copy {
+			Some(v) => v,
+			None => Arc::unwrap_or_clone(self.snap),
+		}
+	}
+}
+
+impl<T> Deref for SyncCowRef<'_, T> {
+	type Target = T;
+
+	#[inline]
+	fn deref(&self) -> &Self::Target {
+		self.get_ref()
+	}
+}
+
+impl<T: Clone> DerefMut for SyncCowRef<'_, T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.get_mut()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn commit_conflict() {
+		let cell = SyncCowCell::new(0);
+		let mut a = cell.borrow();
+		let mut b = cell.borrow();
+		*a = 1;
+		*b = 2;
+		a.commit().unwrap();
+		assert_eq!(b.commit(), Err(CommitError::Conflict(2)));
+		assert_eq!(*cell.load(), 1);
+	}
+
+	#[test]
+	fn concurrent_updates() {
+		let cell = SyncCowCell::new(0u32);
+		std::thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..100 {
+						cell.update(|v| *v += 1);
+					}
+				});
+			}
+		});
+		assert_eq!(cell.into_inner(), 400);
+	}
+}
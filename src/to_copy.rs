@@ -0,0 +1,167 @@
+//! Making private copies of borrowed values.
+
+use core::borrow::Borrow;
+
+/// The type of the owned copies of a value, such as [`String`] for
+/// [`str`].
+///
+/// Every sized type is its own copy, whether it can be cloned or not,
+/// so that borrows of any sized value can be made and read. Unsized
+/// types implement this along with [`ToCopy`].
+///
+/// [`String`]: alloc::string::String
+pub trait OwnedCopy {
+	/// The type of the copy.
+	type Owned: Borrow<Self>;
+}
+
+impl<T> OwnedCopy for T {
+	type Owned = T;
+}
+
+/// Making an owned copy of a borrowed value, like `ToOwned`, but
+/// available without `alloc`.
+///
+/// This is what a [`CowRef`](crate::CowRef) uses to make its private
+/// copy. It is implemented for every [`Clone`] type, whose copy is a
+/// clone of the value, and for unsized types such as [`str`] and
+/// slices, whose copy is their owned counterpart.
+pub trait ToCopy: OwnedCopy {
+	/// Make an owned copy of `self`.
+	fn to_copy(&self) -> Self::Owned;
+}
+
+impl<T: Clone> ToCopy for T {
+	#[inline]
+	fn to_copy(&self) -> T {
+		self.clone()
+	}
+}
+
+/// The copies made by a [`CowClone`] strategy, which is all a
+/// [`CowRef`] needs to know about its strategy until it makes a copy.
+///
+/// [`CowRef`]: crate::CowRef
+pub trait CowCopy<T: ?Sized> {
+	/// The type of the copy.
+	type Owned: Borrow<T>;
+
+	/// Dispose of a copy that is no longer needed, such as the copy
+	/// of a dropped borrow, or the value replaced by a commit. By
+	/// default, it is simply dropped.
+	#[inline]
+	fn recycle(&mut self, copy: Self::Owned) {
+		drop(copy);
+	}
+}
+
+/// A strategy for making the private copy of a [`CowRef`].
+///
+/// By default, borrows make their copy with [`ToCopy`] through
+/// [`DefaultClone`]. Other strategies can be passed to
+/// [`CowCell::borrow_with`] to copy values differently, such as
+/// sharing the `Rc`s of a graph instead of deep cloning them, cloning
+/// into a recycled buffer, or duplicating a handle that does not
+/// implement [`Clone`]. Any `FnMut(&T) -> T` closure is a strategy.
+///
+/// [`CowRef`]: crate::CowRef
+/// [`CowCell::borrow_with`]: crate::CowCell::borrow_with
+pub trait CowClone<T: ?Sized>: CowCopy<T> {
+	/// Make an owned copy of `val`.
+	fn cow_clone(&mut self, val: &T) -> Self::Owned;
+}
+
+/// The default [`CowClone`] strategy, which copies values with
+/// [`ToCopy::to_copy`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultClone;
+
+impl<T: ?Sized + OwnedCopy> CowCopy<T> for DefaultClone {
+	type Owned = T::Owned;
+}
+
+impl<T: ?Sized + ToCopy> CowClone<T> for DefaultClone {
+	#[inline]
+	fn cow_clone(&mut self, val: &T) -> T::Owned {
+		val.to_copy()
+	}
+}
+
+impl<T, F: FnMut(&T) -> T> CowCopy<T> for F {
+	type Owned = T;
+}
+
+impl<T, F: FnMut(&T) -> T> CowClone<T> for F {
+	#[inline]
+	fn cow_clone(&mut self, val: &T) -> T {
+		self(val)
+	}
+}
+
+#[cfg(feature = "alloc")]
+mod unsized_impls {
+	use super::{OwnedCopy, ToCopy};
+	use alloc::ffi::CString;
+	use alloc::string::String;
+	use alloc::vec::Vec;
+	use core::ffi::CStr;
+
+	impl OwnedCopy for str {
+		type Owned = String;
+	}
+
+	impl ToCopy for str {
+		#[inline]
+		fn to_copy(&self) -> String {
+			self.into()
+		}
+	}
+
+	impl<T> OwnedCopy for [T] {
+		type Owned = Vec<T>;
+	}
+
+	impl<T: Clone> ToCopy for [T] {
+		#[inline]
+		fn to_copy(&self) -> Vec<T> {
+			self.to_vec()
+		}
+	}
+
+	impl OwnedCopy for CStr {
+		type Owned = CString;
+	}
+
+	impl ToCopy for CStr {
+		#[inline]
+		fn to_copy(&self) -> CString {
+			self.into()
+		}
+	}
+
+	#[cfg(feature = "std")]
+	impl OwnedCopy for std::ffi::OsStr {
+		type Owned = std::ffi::OsString;
+	}
+
+	#[cfg(feature = "std")]
+	impl ToCopy for std::ffi::OsStr {
+		#[inline]
+		fn to_copy(&self) -> std::ffi::OsString {
+			self.into()
+		}
+	}
+
+	#[cfg(feature = "std")]
+	impl OwnedCopy for std::path::Path {
+		type Owned = std::path::PathBuf;
+	}
+
+	#[cfg(feature = "std")]
+	impl ToCopy for std::path::Path {
+		#[inline]
+		fn to_copy(&self) -> std::path::PathBuf {
+			self.into()
+		}
+	}
+}
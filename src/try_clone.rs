@@ -0,0 +1,203 @@
+//! Making private copies without aborting on allocation failure.
+
+use crate::{AllocError, CowRef};
+
+/// Fallible cloning, which reports allocation failures instead of
+/// aborting.
+///
+/// This is what [`CowRef::try_get_mut`] and [`CowRef::try_into_inner`]
+/// use to make their copy. It is implemented for primitives, which
+/// never fail to clone, and with the `alloc` feature for collections,
+/// which reserve their memory with `try_reserve` before cloning their
+/// elements one by one. `BTreeMap` and `BTreeSet` allocate their nodes
+/// one at a time without a fallible API, so they do not implement it.
+pub trait TryClone: Sized {
+	/// Try to make a clone of `self`.
+	fn try_clone(&self) -> Result<Self, AllocError>;
+}
+
+macro_rules! impl_copy {
+	($($ty:ty),*) => {$(
+		impl TryClone for $ty {
+			#[inline]
+			fn try_clone(&self) -> Result<Self, AllocError> {
+				Ok(*self)
+			}
+		}
+	)*};
+}
+
+impl_copy!(
+	bool,
+	char,
+	u8,
+	u16,
+	u32,
+	u64,
+	u128,
+	usize,
+	i8,
+	i16,
+	i32,
+	i64,
+	i128,
+	isize,
+	f32,
+	f64,
+	()
+);
+
+impl<T: TryClone> TryClone for Option<T> {
+	#[inline]
+	fn try_clone(&self) -> Result<Self, AllocError> {
+		self.as_ref().map(T::try_clone).transpose()
+	}
+}
+
+#[cfg(feature = "alloc")]
+mod collections {
+	use super::TryClone;
+	use crate::AllocError;
+	use alloc::collections::VecDeque;
+	use alloc::string::String;
+	use alloc::vec::Vec;
+
+	impl TryClone for String {
+		fn try_clone(&self) -> Result<Self, AllocError> {
+			let mut s = String::new();
+			s.try_reserve_exact(self.len())?;
+			s.push_str(self);
+			Ok(s)
+		}
+	}
+
+	impl<T: TryClone> TryClone for Vec<T> {
+		fn try_clone(&self) -> Result<Self, AllocError> {
+			let mut v = Vec::new();
+			v.try_reserve_exact(self.len())?;
+			for x in self {
+				v.push(x.try_clone()?);
+			}
+			Ok(v)
+		}
+	}
+
+	impl<T: TryClone> TryClone for VecDeque<T> {
+		fn try_clone(&self) -> Result<Self, AllocError> {
+			let mut v = VecDeque::new();
+			v.try_reserve_exact(self.len())?;
+			for x in self {
+				v.push_back(x.try_clone()?);
+			}
+			Ok(v)
+		}
+	}
+
+	#[cfg(feature = "std")]
+	impl<K, V, S> TryClone for std::collections::HashMap<K, V, S>
+	where
+		K: TryClone + Eq + core::hash::Hash,
+		V: TryClone,
+		S: core::hash::BuildHasher + Clone,
+	{
+		fn try_clone(&self) -> Result<Self, AllocError> {
+			let mut m = Self::with_hasher(self.hasher().clone());
+			m.try_reserve(self.len())?;
+			for (k, v) in self {
+				m.insert(k.try_clone()?, v.try_clone()?);
+			}
+			Ok(m)
+		}
+	}
+}
+
+impl<'a, T: TryClone> CowRef<'a, T> {
+	/// Get a mutable reference to the copy of the inner value, trying
+	/// to make it if necessary. On failure, no copy is made and the
+	/// borrow keeps reading the original value.
+	///
+	/// ```rust
+	/// # #[cfg(feature = "alloc")] {
+	/// use cowcell::CowCell;
+	///
+	/// let cell = CowCell::new(vec![1, 2]);
+	/// let mut borrow = cell.borrow();
+	/// borrow.try_get_mut().unwrap().push(3);
+	/// assert_eq!(*borrow, [1, 2, 3]);
+	/// # }
+	/// ```
+	pub fn try_get_mut(&mut self) -> Result<&mut T, AllocError> {
+		if !self.is_cloned() {
+			let copy = self.get_ref().try_clone()?;
+			self.set_copy(copy);
+		}
+		Ok(self.copy.as_mut().unwrap())
+	}
+
+	/// Consume the [`CowRef`], retrieving the copy of the inner value.
+	/// This tries to make the copy if it was not already made.
+	pub fn try_into_inner(mut self) -> Result<T, AllocError> {
+		match self.copy.take() {
+			Some(v) => Ok(v),
+			None => self.get_ref().try_clone(),
+		}
+	}
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+	use super::*;
+	use crate::CowCell;
+
+	#[derive(Debug)]
+	struct Fails;
+
+	impl TryClone for Fails {
+		fn try_clone(&self) -> Result<Self, AllocError> {
+			Err(AllocError)
+		}
+	}
+
+	#[test]
+	fn failed_copy() {
+		let cell = CowCell::new(vec![Fails]);
+		let mut borrow = cell.borrow();
+		assert_eq!(borrow.try_get_mut().err(), Some(AllocError));
+		assert!(!borrow.is_cloned());
+		assert_eq!(borrow.try_into_inner().err(), Some(AllocError));
+	}
+
+	#[test]
+	fn try_into_inner() {
+		let cell = CowCell::new(String::from("abc"));
+		assert_eq!(cell.borrow().try_into_inner().unwrap(), "abc");
+		let mut borrow = cell.borrow();
+		borrow.try_get_mut().unwrap().push('d');
+		assert_eq!(borrow.try_into_inner().unwrap(), "abcd");
+	}
+
+	#[test]
+	fn without_clone() {
+		#[derive(Debug, PartialEq)]
+		struct Handle(u32);
+
+		impl TryClone for Handle {
+			fn try_clone(&self) -> Result<Self, AllocError> {
+				Ok(Self(self.0))
+			}
+		}
+
+		let cell = CowCell::new(Handle(1));
+		let mut borrow = cell.borrow();
+		borrow.try_get_mut().unwrap().0 = 2;
+		assert_eq!(borrow.try_into_inner(), Ok(Handle(2)));
+		assert_eq!(cell.borrow().try_into_inner(), Ok(Handle(1)));
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn hash_map() {
+		let map = std::collections::HashMap::from([(1, vec![2])]);
+		assert_eq!(map.try_clone().unwrap(), map);
+	}
+}
@@ -0,0 +1,307 @@
+//! Overlay borrows of vectors that only store the modified elements.
+
+use crate::state::ReadGuard;
+use crate::{CommitError, CowCell};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Index, IndexMut};
+
+/// A borrow of a vector that records the changes made to it, instead
+/// of cloning the whole vector.
+///
+/// The borrow keeps the logical length of the vector, the elements
+/// that replace those of the original vector, and the elements pushed
+/// past its end. Like a [`CowMap`](crate::CowMap), it reads the
+/// original value of the cell for as long as it is alive, and its
+/// changes are published with [`CowVec::commit`], which applies them
+/// to the cell in place.
+///
+/// ```rust
+/// use cowcell::CowCell;
+///
+/// let cell = CowCell::new(vec![1, 2, 3]);
+/// let mut vec = cell.overlay_vec();
+/// vec.truncate(2);
+/// vec.push(4);
+/// vec[0] = 0;
+///
+/// assert_eq!(vec.len(), 3);
+/// assert_eq!(vec.iter().collect::<Vec<_>>(), [&0, &2, &4]);
+/// assert_eq!(vec.materialize(), [0, 2, 4]);
+///
+/// // The original vector has not been modified.
+/// assert_eq!(*vec.get_cell().borrow(), [1, 2, 3]);
+/// ```
+pub struct CowVec<'a, T> {
+	ptr: &'a CowCell<Vec<T>>,
+	read: ReadGuard<'a>,
+	/// The number of elements of the original vector that are kept.
+	kept: usize,
+	replaced: BTreeMap<usize, T>,
+	tail: Vec<T>,
+}
+
+impl<T> CowCell<Vec<T>> {
+	/// Create a new borrow that records the changes made to the
+	/// vector.
+	#[inline]
+	pub fn overlay_vec(&self) -> CowVec<'_, T> {
+		CowVec::new(self)
+	}
+}
+
+impl<'a, T> CowVec<'a, T> {
+	/// A new borrow from a [`CowCell`].
+	pub fn new(ptr: &'a CowCell<Vec<T>>) -> Self {
+		let read = ptr.state.read();
+		// SAFETY: we hold a read on the cell.
+		let kept = unsafe { ptr.get_unchecked() }.len();
+		Self {
+			ptr,
+			read,
+			kept,
+			replaced: BTreeMap::new(),
+			tail: Vec::new(),
+		}
+	}
+
+	/// Returns a reference to the [`CowCell`] that originated this
+	/// borrow.
+	#[inline]
+	pub const fn get_cell(&self) -> &CowCell<Vec<T>> {
+		self.ptr
+	}
+
+	/// The original vector.
+	#[inline]
+	fn base(&self) -> &[T] {
+		// SAFETY: this borrow holds a read on the cell.
+		unsafe { self.ptr.get_unchecked() }
+	}
+
+	/// Returns the number of elements in the vector.
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.kept + self.tail.len()
+	}
+
+	/// Returns [`true`] if the vector has no elements.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Returns [`true`] if changes have been made to the vector.
+	#[inline]
+	pub fn is_modified(&self) -> bool {
+		self.kept != self.base().len()
+			|| !self.replaced.is_empty()
+			|| !self.tail.is_empty()
+	}
+
+	/// Returns a reference to the element at index `i`.
+	pub fn get(&self, i: usize) -> Option<&T> {
+		if i >= self.kept {
+			return self.tail.get(i - self.kept);
+		}
+		self.replaced.get(&i).or_else(|| self.base().get(i))
+	}
+
+	/// Replace the element at index `i`, without copying the original
+	/// one.
+	///
+	/// # Panics
+	///
+	/// If `i` is out of bounds.
+	pub fn set(&mut self, i: usize, val: T) {
+		assert!(i < self.len(), "index out of bounds");
+		if i >= self.kept {
+			self.tail[i - self.kept] = val;
+		} else {
+			self.replaced.insert(i, val);
+		}
+	}
+
+	/// Append an element to the end of the vector.
+	#[inline]
+	pub fn push(&mut self, val: T) {
+		self.tail.push(val);
+	}
+
+	/// Shorten the vector to `len` elements. This has no effect if it
+	/// is already shorter.
+	pub fn truncate(&mut self, len: usize) {
+		if len >= self.kept {
+			self.tail.truncate(len - self.kept);
+		} else {
+			self.tail.clear();
+			self.replaced.split_off(&len);
+			self.kept = len;
+		}
+	}
+
+	/// Returns an iterator over the elements of the vector.
+	#[inline]
+	pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+		(0..self.len()).map(|i| &self[i])
+	}
+
+	/// Write the changes into the originating [`CowCell`], handing
+	/// back the borrow in [`CommitError::Busy`] if other borrows are
+	/// reading the cell.
+	pub fn commit(mut self) -> Result<(), CommitError<Self>> {
+		if !self.is_modified() {
+			return Ok(());
+		}
+		let (kept, replaced, tail) =
+			(self.kept, &mut self.replaced, &mut self.tail);
+		let done = self.ptr.commit_in_place(
+			&mut self.read,
+			|vec, displaced: &mut Vec<T>| {
+				*displaced = vec.split_off(kept);
+				for (i, val) in core::mem::take(replaced) {
+					displaced
+						.push(core::mem::replace(&mut vec[i], val));
+				}
+				vec.append(tail);
+			},
+		);
+		if !done {
+			return Err(CommitError::Busy(self));
+		}
+		Ok(())
+	}
+}
+
+impl<'a, T: Clone> CowVec<'a, T> {
+	/// Returns a mutable reference to the element at index `i`,
+	/// copying it from the original vector if necessary.
+	pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+		if i >= self.kept {
+			return self.tail.get_mut(i - self.kept);
+		}
+		if !self.replaced.contains_key(&i) {
+			let val = self.base()[i].clone();
+			self.replaced.insert(i, val);
+		}
+		self.replaced.get_mut(&i)
+	}
+
+	/// Remove the last element of the vector and return it, copying it
+	/// from the original vector if necessary.
+	pub fn pop(&mut self) -> Option<T> {
+		if let Some(val) = self.tail.pop() {
+			return Some(val);
+		}
+		self.kept = self.kept.checked_sub(1)?;
+		let i = self.kept;
+		self.replaced
+			.remove(&i)
+			.or_else(|| Some(self.base()[i].clone()))
+	}
+
+	/// Build the vector with the changes applied, leaving the borrow
+	/// untouched.
+	#[inline]
+	pub fn materialize(&self) -> Vec<T> {
+		self.iter().cloned().collect()
+	}
+
+	/// Consume the [`CowVec`], retrieving the vector with the changes
+	/// applied. Only the kept elements that were not replaced are
+	/// cloned.
+	pub fn into_inner(mut self) -> Vec<T> {
+		let mut vec = Vec::with_capacity(self.len());
+		let replaced = core::mem::take(&mut self.replaced);
+		let mut replaced = replaced.into_iter().peekable();
+		for (i, val) in self.base()[..self.kept].iter().enumerate() {
+			match replaced.next_if(|(j, _)| *j == i) {
+				Some((_, new)) => vec.push(new),
+				None => vec.push(val.clone()),
+			}
+		}
+		vec.append(&mut self.tail);
+		vec
+	}
+}
+
+impl<T> Index<usize> for CowVec<'_, T> {
+	type Output = T;
+
+	#[inline]
+	fn index(&self, i: usize) -> &T {
+		self.get(i).expect("index out of bounds")
+	}
+}
+
+impl<T: Clone> IndexMut<usize> for CowVec<'_, T> {
+	#[inline]
+	fn index_mut(&mut self, i: usize) -> &mut T {
+		self.get_mut(i).expect("index out of bounds")
+	}
+}
+
+impl<T: fmt::Debug> fmt::Debug for CowVec<'_, T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_list().entries(self.iter()).finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn truncate_and_pop() {
+		let cell = CowCell::new(vec![1, 2, 3, 4]);
+		let mut vec = cell.overlay_vec();
+		vec.set(3, 40);
+		vec.set(1, 20);
+		vec.truncate(3);
+		vec.push(5);
+		assert_eq!(vec.pop(), Some(5));
+		assert_eq!(vec.pop(), Some(3));
+		assert_eq!(vec.get(2), None);
+		vec.push(6);
+		assert_eq!(vec.into_inner(), [1, 20, 6]);
+	}
+
+	#[test]
+	fn commit() {
+		let cell = CowCell::new(vec![1, 2, 3]);
+		let mut vec = cell.overlay_vec();
+		vec.truncate(1);
+		vec[0] += 1;
+		vec.push(4);
+		vec.commit().unwrap();
+		assert_eq!(*cell.borrow(), [2, 4]);
+		assert_eq!(cell.version(), 1);
+		assert!(!cell.overlay_vec().is_modified());
+	}
+
+	#[test]
+	fn into_inner_clones_kept() {
+		use core::sync::atomic::{AtomicUsize, Ordering};
+
+		static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+		#[derive(Debug, PartialEq)]
+		struct Counted(u32);
+
+		impl Clone for Counted {
+			fn clone(&self) -> Self {
+				CLONES.fetch_add(1, Ordering::Relaxed);
+				Self(self.0)
+			}
+		}
+
+		let cell = CowCell::new((0..4).map(Counted).collect());
+		let mut vec = cell.overlay_vec();
+		vec.set(1, Counted(10));
+		vec.set(3, Counted(30));
+		vec.push(Counted(4));
+		assert_eq!(vec.into_inner(), [0, 10, 2, 30, 4].map(Counted));
+		assert_eq!(CLONES.load(Ordering::Relaxed), 2);
+	}
+}
@@ -0,0 +1,184 @@
+//! Clone-on-write views over arbitrary sources.
+
+use crate::{OwnedCopy, ToCopy};
+use core::borrow::{Borrow, BorrowMut};
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+/// A view with clone-on-write semantics over any [`Deref`] source,
+/// such as a `&T`, an `Arc<T>`, an `Rc<T>` or a `MutexGuard<T>`.
+///
+/// Unlike a [`CowRef`](crate::CowRef), a view is not tied to a
+/// [`CowCell`](crate::CowCell), so its copy cannot be committed back
+/// to the source. It holds on to the source for as long as it reads
+/// from it, and releases it once a copy is made. A view over an owned
+/// source such as an `Arc<T>` borrows nothing, and is thus `'static`
+/// if `T` is.
+///
+/// ```rust
+/// use cowcell::CowView;
+/// use std::sync::Arc;
+///
+/// let shared = Arc::new(vec![1, 2]);
+/// let mut view = CowView::new(Arc::clone(&shared));
+/// assert_eq!(*view, [1, 2]);
+///
+/// // The view copies the shared value on mutable access.
+/// view.push(3);
+/// assert_eq!(*view, [1, 2, 3]);
+/// assert_eq!(*shared, [1, 2]);
+/// assert_eq!(Arc::strong_count(&shared), 1);
+/// ```
+pub struct CowView<S: Deref>
+where
+	S::Target: OwnedCopy,
+{
+	src: Option<S>,
+	copy: Option<<S::Target as OwnedCopy>::Owned>,
+}
+
+impl<S: Deref> CowView<S>
+where
+	S::Target: OwnedCopy,
+{
+	/// A new view reading from the given source.
+	#[inline]
+	pub const fn new(src: S) -> Self {
+		Self {
+			src: Some(src),
+			copy: None,
+		}
+	}
+
+	/// Returns the source of this view, or [`None`] if it was
+	/// released after making a copy.
+	#[inline]
+	pub const fn source(&self) -> Option<&S> {
+		self.src.as_ref()
+	}
+
+	/// Get an immutable reference to the inner value.
+	#[inline]
+	pub fn get_ref(&self) -> &S::Target {
+		match (&self.copy, &self.src) {
+			(Some(v), _) => v.borrow(),
+			(None, Some(src)) => src,
+			(None, None) => unreachable!(),
+		}
+	}
+
+	/// Returns [`true`] if this [`CowView`] has made a copy of the
+	/// original value.
+	#[inline]
+	pub const fn is_cloned(&self) -> bool {
+		self.copy.is_some()
+	}
+}
+
+impl<S: Deref> CowView<S>
+where
+	S::Target: ToCopy,
+{
+	/// Get a mutable reference to the copy of the inner value, making
+	/// it and releasing the source if necessary.
+	#[inline]
+	pub fn get_mut(
+		&mut self,
+	) -> &mut <S::Target as OwnedCopy>::Owned {
+		if self.copy.is_none() {
+			let copy = self.get_ref().to_copy();
+			self.copy = Some(copy);
+			self.src = None;
+		}
+		self.copy.as_mut().unwrap()
+	}
+
+	/// Consume the [`CowView`], retrieving the copy of the inner
+	/// value. This makes the copy if it was not already made.
+	#[inline]
+	pub fn into_inner(mut self) -> <S::Target as OwnedCopy>::Owned {
+		match self.copy.take() {
+			Some(v) => v,
+			None => self.get_ref().to_copy(),
+		}
+	}
+}
+
+impl<S: Deref> Deref for CowView<S>
+where
+	S::Target: OwnedCopy,
+{
+	type Target = S::Target;
+
+	#[inline]
+	fn deref(&self) -> &Self::Target {
+		self.get_ref()
+	}
+}
+
+impl<S: Deref> DerefMut for CowView<S>
+where
+	S::Target: ToCopy,
+	<S::Target as OwnedCopy>::Owned: BorrowMut<S::Target>,
+{
+	#[inline]
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.get_mut().borrow_mut()
+	}
+}
+
+impl<S: Deref> fmt::Debug for CowView<S>
+where
+	S::Target: OwnedCopy + fmt::Debug,
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("CowView")
+			.field("val", &self.get_ref())
+			.field("is_cloned", &self.is_cloned())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(feature = "alloc")]
+	use std::rc::Rc;
+	use std::sync::Mutex;
+
+	#[cfg(feature = "alloc")]
+	fn assert_static<T: 'static>(_: &T) {}
+
+	#[test]
+	fn from_ref() {
+		let val = String::from("abc");
+		let mut view = CowView::new(&val);
+		assert!(!view.is_cloned());
+		view.push('d');
+		assert_eq!(view.into_inner(), "abcd");
+		assert_eq!(val, "abc");
+	}
+
+	#[test]
+	#[cfg(feature = "alloc")]
+	fn from_rc() {
+		let rc: Rc<str> = Rc::from("abc");
+		let mut view = CowView::new(Rc::clone(&rc));
+		assert_static(&view);
+		assert_eq!(Rc::strong_count(&rc), 2);
+		view.get_mut().push('d');
+		assert!(view.source().is_none());
+		assert_eq!(Rc::strong_count(&rc), 1);
+		assert_eq!(&*view, "abcd");
+	}
+
+	#[test]
+	fn from_guard() {
+		let lock = Mutex::new(1);
+		let mut view = CowView::new(lock.lock().unwrap());
+		assert!(lock.try_lock().is_err());
+		*view += 1;
+		assert_eq!(*lock.try_lock().unwrap(), 1);
+		assert_eq!(*view, 2);
+	}
+}